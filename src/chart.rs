@@ -0,0 +1,240 @@
+use crate::context::{字源上下文, 字源元素安排, 字源决策};
+use chai::{元素, 错误};
+use std::{fs::File, io::Write, path::Path};
+
+/// 标准键盘上的行，字母表中不在这些行里的键放在最后一行
+const 键盘行: [&str; 4] = ["1234567890-=", "qwertyuiop[]", "asdfghjkl;'", "zxcvbnm,./"];
+/// SVG 中每行排列的字根数
+const 每行字根数: usize = 5;
+
+pub struct 字根项 {
+    pub 名称: String,
+    /// 按当前决策拆分时用到该字根的字频之和
+    pub 频率: u64,
+    /// 归并的直接目标，没有归并时为空
+    pub 归并目标: Option<String>,
+}
+
+pub struct 键位字根 {
+    pub 键: char,
+    /// 按频率降序排列
+    pub 字根: Vec<字根项>,
+}
+
+/// 沿归并链找到元素最终所在的键，链中有未选取的元素或成环时返回 None
+fn 最终键位(决策: &字源决策, 元素: 元素) -> Option<char> {
+    let mut 当前 = 元素;
+    for _ in 0..决策.元素.len() {
+        match 决策.元素[当前] {
+            字源元素安排::键位(键) => return Some(键),
+            字源元素安排::归并(目标) => 当前 = 目标,
+            字源元素安排::未选取 => return None,
+        }
+    }
+    None
+}
+
+/// 统计每个字根在当前决策下的使用频率，拆分方式的选取与编码器一致
+fn 字根频率(上下文: &字源上下文, 决策: &字源决策) -> Vec<u64> {
+    let 映射 = 决策.线性化(&上下文.棱镜);
+    let mut 频率 = vec![0; 决策.元素.len()];
+    for 一字信息项 in &上下文.一字信息 {
+        for 块序号 in &一字信息项.字块 {
+            let 拆分方式 = 上下文.动态拆分[*块序号]
+                .iter()
+                .find(|x| x.iter().all(|y| *y == 0 || 映射[*y] != 0));
+            for 字根 in 拆分方式.into_iter().flatten() {
+                if *字根 != 0 {
+                    频率[*字根] += 一字信息项.频率;
+                }
+            }
+        }
+    }
+    频率
+}
+
+/// 按键盘布局的顺序整理每个键上的字根
+pub fn 整理字根(上下文: &字源上下文, 决策: &字源决策) -> Vec<Vec<键位字根>> {
+    let 频率 = 字根频率(上下文, 决策);
+    let 按键: Vec<char> = 上下文
+        .配置
+        .form
+        .alphabet
+        .chars()
+        .filter(|x| *x != '_')
+        .collect();
+    let mut 布局: Vec<Vec<char>> = 键盘行
+        .iter()
+        .map(|行| 行.chars().filter(|x| 按键.contains(x)).collect())
+        .collect();
+    let 其他键: Vec<char> = 按键
+        .iter()
+        .filter(|x| !键盘行.iter().any(|行| 行.contains(**x)))
+        .copied()
+        .collect();
+    布局.push(其他键);
+    布局.retain(|行| !行.is_empty());
+    布局
+        .into_iter()
+        .map(|行| {
+            行.into_iter()
+                .map(|键| {
+                    let mut 字根: Vec<_> = 上下文
+                        .决策空间
+                        .字根
+                        .iter()
+                        .filter(|x| 最终键位(决策, **x) == Some(键))
+                        .map(|x| 字根项 {
+                            名称: 上下文.棱镜.数字转元素[x].clone(),
+                            频率: 频率[*x],
+                            归并目标: match 决策.元素[*x] {
+                                字源元素安排::归并(目标) => {
+                                    Some(上下文.棱镜.数字转元素[&目标].clone())
+                                }
+                                _ => None,
+                            },
+                        })
+                        .collect();
+                    字根.sort_by(|a, b| b.频率.cmp(&a.频率));
+                    键位字根 { 键, 字根 }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn 转义(文本: &str) -> String {
+    文本
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn 生成标记文本(布局: &[Vec<键位字根>]) -> String {
+    let mut 文本 = String::from("# 字根图\n\n| 键 | 字根 | 归并字根 |\n| --- | --- | --- |\n");
+    for 键位 in 布局.iter().flatten() {
+        let 字根: Vec<_> = 键位
+            .字根
+            .iter()
+            .filter(|x| x.归并目标.is_none())
+            .map(|x| x.名称.as_str())
+            .collect();
+        let 归并字根: Vec<_> = 键位
+            .字根
+            .iter()
+            .filter_map(|x| Some(format!("{}→{}", x.名称, x.归并目标.as_ref()?)))
+            .collect();
+        // 竖线会破坏表格
+        let 字根 = 字根.join(" ").replace('|', "\\|");
+        let 归并字根 = 归并字根.join(" ").replace('|', "\\|");
+        文本 += &format!("| {} | {字根} | {归并字根} |\n", 键位.键);
+    }
+    文本
+}
+
+fn 生成网页(布局: &[Vec<键位字根>]) -> String {
+    let mut 文本 = String::from(
+        "<!DOCTYPE html>\n<html lang=\"zh\">\n<head>\n<meta charset=\"utf-8\">\n<title>字根图</title>\n<style>\n\
+         .行 { display: flex; gap: 4px; margin-bottom: 4px; }\n\
+         .键 { width: 120px; min-height: 90px; border: 1px solid #888; border-radius: 6px; padding: 4px; }\n\
+         .键名 { font-weight: bold; color: #c00; }\n\
+         .归并 { color: #999; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    for (行号, 行) in 布局.iter().enumerate() {
+        文本 += &format!(
+            "<div class=\"行\" style=\"margin-left: {}px\">\n",
+            行号 * 20
+        );
+        for 键位 in 行 {
+            文本 += &format!(
+                "<div class=\"键\"><div class=\"键名\">{}</div>",
+                转义(&键位.键.to_string())
+            );
+            for 字根 in &键位.字根 {
+                match &字根.归并目标 {
+                    Some(目标) => {
+                        文本 += &format!(
+                            "<span class=\"归并\" title=\"归并于 {}\">{}</span> ",
+                            转义(目标),
+                            转义(&字根.名称)
+                        )
+                    }
+                    None => 文本 += &format!("<span>{}</span> ", 转义(&字根.名称)),
+                }
+            }
+            文本 += "</div>\n";
+        }
+        文本 += "</div>\n";
+    }
+    文本 += "</body>\n</html>\n";
+    文本
+}
+
+fn 生成矢量图(布局: &[Vec<键位字根>]) -> String {
+    let (键宽, 键距, 行高, 边距) = (120, 6, 22, 10);
+    let 最多行数 = 布局
+        .iter()
+        .flatten()
+        .map(|x| x.字根.len().div_ceil(每行字根数))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let 键高 = 30 + 最多行数 * 行高;
+    let 最多键数 = 布局.iter().map(|x| x.len()).max().unwrap_or(0);
+    let 宽度 = 边距 * 2 + 布局.len() * 20 + 最多键数 * (键宽 + 键距);
+    let 高度 = 边距 * 2 + 布局.len() * (键高 + 键距);
+    let mut 文本 = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{宽度}\" height=\"{高度}\" font-size=\"16\">\n"
+    );
+    for (行号, 行) in 布局.iter().enumerate() {
+        let 纵坐标 = 边距 + 行号 * (键高 + 键距);
+        for (列号, 键位) in 行.iter().enumerate() {
+            let 横坐标 = 边距 + 行号 * 20 + 列号 * (键宽 + 键距);
+            文本 += &format!(
+                "<rect x=\"{横坐标}\" y=\"{纵坐标}\" width=\"{键宽}\" height=\"{键高}\" rx=\"6\" fill=\"none\" stroke=\"#888\"/>\n"
+            );
+            文本 += &format!(
+                "<text x=\"{}\" y=\"{}\" font-weight=\"bold\" fill=\"#c00\">{}</text>\n",
+                横坐标 + 6,
+                纵坐标 + 20,
+                转义(&键位.键.to_string())
+            );
+            for (行内序号, 一行字根) in 键位.字根.chunks(每行字根数).enumerate() {
+                文本 += &format!(
+                    "<text x=\"{}\" y=\"{}\">",
+                    横坐标 + 6,
+                    纵坐标 + 20 + (行内序号 + 1) * 行高
+                );
+                for 字根 in 一行字根 {
+                    let 颜色 = if 字根.归并目标.is_some() {
+                        "#999"
+                    } else {
+                        "#000"
+                    };
+                    文本 += &format!("<tspan fill=\"{颜色}\">{} </tspan>", 转义(&字根.名称));
+                }
+                文本 += "</text>\n";
+            }
+        }
+    }
+    文本 += "</svg>\n";
+    文本
+}
+
+/// 在目录中输出 Markdown、HTML 和 SVG 三种字根图
+pub fn 输出字根图(
+    上下文: &字源上下文, 决策: &字源决策, 目录: &Path
+) -> Result<(), 错误> {
+    std::fs::create_dir_all(目录)?;
+    let 布局 = 整理字根(上下文, 决策);
+    for (文件名, 内容) in [
+        ("字根图.md", 生成标记文本(&布局)),
+        ("字根图.html", 生成网页(&布局)),
+        ("字根图.svg", 生成矢量图(&布局)),
+    ] {
+        File::create(目录.join(文件名))?.write_all(内容.as_bytes())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use chai::interfaces::command_line::{命令, 数据参数, 默认命令行参数};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// 字源方案的命令行参数，在 chai 默认命令的基础上增加字源专用的命令
+#[derive(Parser, Clone)]
+#[command(name = "字源方案优化系统")]
+#[command(version, about, long_about)]
+#[command(propagate_version = true)]
+pub struct 字源命令行参数 {
+    #[command(subcommand)]
+    pub command: 字源命令,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum 字源命令 {
+    #[command(about = "使用方案文件和拆分表计算出字词编码并统计各类评测指标")]
+    Encode {
+        #[command(flatten)]
+        data: 数据参数,
+    },
+    #[command(about = "基于配置文件优化决策")]
+    Optimize {
+        #[command(flatten)]
+        data: 数据参数,
+        /// 优化时使用的线程数
+        #[arg(short, long, default_value = "1")]
+        threads: usize,
+        /// 从之前输出的配置文件继续优化，各线程依次轮流使用这些文件中的决策，文件数不能多于线程数
+        #[arg(long, value_name = "FILE", num_args = 1..)]
+        resume: Vec<PathBuf>,
+    },
+    #[command(about = "检查方案文件和拆分数据的一致性，不进行编码")]
+    Validate {
+        #[command(flatten)]
+        data: 数据参数,
+    },
+    #[command(about = "计算编码并导出为输入法平台可以直接使用的码表")]
+    Export {
+        #[command(flatten)]
+        data: 数据参数,
+        /// 导出文件所在的目录
+        #[arg(short, long, value_name = "DIR", default_value = "export")]
+        output: PathBuf,
+        /// 码表的名称，也用作文件名
+        #[arg(short, long, default_value = "ziyuan")]
+        name: String,
+        /// 码表格式
+        #[arg(short, long, value_enum, default_value = "rime")]
+        format: 导出格式,
+    },
+    #[command(about = "按配置文件中的决策输出 Markdown、HTML 和 SVG 字根图")]
+    Chart {
+        #[command(flatten)]
+        data: 数据参数,
+        /// 字根图所在的目录
+        #[arg(short, long, value_name = "DIR", default_value = "chart")]
+        output: PathBuf,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum 导出格式 {
+    /// Rime 词典和方案
+    Rime,
+    /// Fcitx5 码表文本，可用 libime_tabledict 转换
+    Fcitx5,
+    /// 多多输入法码表
+    Duoduo,
+    /// 小小输入法码表
+    Xiaoxiao,
+}
+
+impl 字源命令行参数 {
+    pub fn 数据(&self) -> &数据参数 {
+        match &self.command {
+            字源命令::Encode { data }
+            | 字源命令::Optimize { data, .. }
+            | 字源命令::Validate { data }
+            | 字源命令::Export { data, .. }
+            | 字源命令::Chart { data, .. } => data,
+        }
+    }
+
+    pub fn 配置路径(&self) -> PathBuf {
+        self.数据()
+            .config
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("config.yaml"))
+    }
+
+    /// 转换为 chai 的默认命令行参数，以复用 chai 的数据加载和结果输出
+    pub fn 默认参数(&self) -> 默认命令行参数 {
+        let command = match &self.command {
+            字源命令::Optimize { data, threads, .. } => 命令::Optimize {
+                data: data.clone(),
+                threads: *threads,
+            },
+            _ => 命令::Encode {
+                data: self.数据().clone(),
+            },
+        };
+        默认命令行参数 { command }
+    }
+}
@@ -0,0 +1,725 @@
+use crate::context::{字源方案, 特简字, 特简码};
+use chai::{棱镜, 错误};
+use serde::Deserialize;
+use serde_yaml::{Value, from_str};
+use std::{fs::read_to_string, iter::zip, path::PathBuf};
+
+/// chai 配置之外的字源扩展字段，和 chai 配置写在同一个配置文件里，
+/// chai 解析时会忽略这些字段
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct 字源配置 {
+    #[serde(default)]
+    pub encoder: 字源编码配置,
+    #[serde(default)]
+    pub optimization: 字源优化配置,
+    #[serde(default)]
+    pub data: 字源数据配置,
+    /// 配置文件的原始内容，输出优化结果时在其上替换决策，保留 chai 不认识的字段
+    #[serde(skip)]
+    pub 原始内容: Value,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct 字源数据配置 {
+    /// 动态拆分数据文件，相对路径按当前工作目录解析
+    pub 动态拆分: PathBuf,
+    /// 固定候选顺序文件，每行一个字词，只取第一列
+    pub 候选顺序: Option<PathBuf>,
+    /// 多音字的每个读音各生成一个编码，按读音频率分别统计
+    pub 多音字: bool,
+    pub 字集: 字源字集,
+    /// 在字集之外额外收录的字，直接列出，空白忽略
+    pub 包含: String,
+    /// 从字集中去掉的字，优先于包含
+    pub 排除: String,
+}
+
+/// 参与编码的汉字范围，含有范围外汉字的多字词一并跳过
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum 字源字集 {
+    /// 既在 GB2312 中又在通用规范汉字表中
+    #[default]
+    交集,
+    /// GB2312
+    #[serde(alias = "GB2312")]
+    国标,
+    /// 通用规范汉字表
+    通规,
+    /// 在 GB2312 或通用规范汉字表中
+    并集,
+    /// 汉字信息中的全部汉字
+    全部,
+}
+
+impl 字源字集 {
+    pub fn 包含(&self, gb2312: bool, 通规: bool) -> bool {
+        match self {
+            字源字集::交集 => gb2312 && 通规,
+            字源字集::国标 => gb2312,
+            字源字集::通规 => 通规,
+            字源字集::并集 => gb2312 || 通规,
+            字源字集::全部 => true,
+        }
+    }
+}
+
+impl Default for 字源数据配置 {
+    fn default() -> Self {
+        Self {
+            动态拆分: PathBuf::from("dynamic_analysis.yaml"),
+            候选顺序: None,
+            多音字: false,
+            字集: 字源字集::default(),
+            包含: String::new(),
+            排除: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct 字源编码配置 {
+    #[serde(default)]
+    pub 方案: 字源方案,
+    #[serde(default)]
+    pub 候选排序: 字源候选排序,
+    /// 简码规则按顺序依次尝试，不填时使用方案对应的默认规则
+    #[serde(default)]
+    pub 简码规则: Vec<字源简码规则>,
+    #[serde(default)]
+    pub 简码分配: 字源简码分配,
+    /// 单字全码的取码规则，按顺序取第一条适用的规则，不填时使用方案对应的默认规则
+    #[serde(default)]
+    pub 取码规则: Vec<字源取码规则>,
+    /// 多字词的取码规则，按顺序取第一条适用的规则，不填时使用默认规则
+    #[serde(default)]
+    pub 词规则: Vec<字源词规则>,
+}
+
+/// 按字数选用的多字词取码规则
+#[derive(Debug, Clone, Deserialize)]
+pub struct 字源词规则 {
+    pub 最少字数: usize,
+    /// 不填表示不限
+    pub 最多字数: Option<usize>,
+    /// 大写字母表示第几个字，小写字母表示该字全码的第几码，Z 和 z 表示最后一个，
+    /// 例如 AaAbBaBb 表示前两字各取前两码
+    pub 公式: String,
+}
+
+impl 字源词规则 {
+    pub fn 适用(&self, 字数: usize) -> bool {
+        字数 >= self.最少字数 && self.最多字数.is_none_or(|x| 字数 <= x)
+    }
+
+    /// 解析为 (字序号, 码序号) 的列表，负数表示从末尾倒数
+    pub fn 解析(&self) -> Result<Vec<(isize, isize)>, String> {
+        let 字符: Vec<char> = self.公式.chars().collect();
+        if 字符.is_empty() || 字符.len() % 2 != 0 {
+            return Err(format!("词规则 {:?} 的长度必须是正偶数", self.公式));
+        }
+        let 序号 = |c: char, z: char, a: char| {
+            if c == z { -1 } else { c as isize - a as isize }
+        };
+        let mut 结果 = vec![];
+        for 对 in 字符.chunks(2) {
+            let (字, 码) = (对[0], 对[1]);
+            if !字.is_ascii_uppercase() || !码.is_ascii_lowercase() {
+                return Err(format!(
+                    "词规则 {:?} 中的 {字}{码} 应为一个大写字母加一个小写字母",
+                    self.公式
+                ));
+            }
+            结果.push((序号(字, 'Z', 'A'), 序号(码, 'z', 'a')));
+        }
+        Ok(结果)
+    }
+}
+
+/// 列出从起点开始没有被任何 (最少, 最多) 范围覆盖的数目，全部覆盖时返回 None
+fn 未覆盖数目(范围: &[(usize, Option<usize>)], 起点: usize) -> Option<String> {
+    let 适用 = |数目: usize| {
+        范围
+            .iter()
+            .any(|(最少, 最多)| 数目 >= *最少 && 最多.is_none_or(|x| 数目 <= x))
+    };
+    // 超过所有上下界之后，是否适用就不再随数目变化
+    let 上界 = 范围
+        .iter()
+        .map(|(最少, 最多)| (*最少).max(最多.unwrap_or(0)) + 1)
+        .max()
+        .unwrap_or(起点)
+        .max(起点);
+    let mut 缺口: Vec<_> = (起点..上界)
+        .filter(|x| !适用(*x))
+        .map(|x| x.to_string())
+        .collect();
+    if !适用(上界) {
+        缺口.push(format!("{上界} 及以上"));
+    }
+    (!缺口.is_empty()).then(|| 缺口.join("、"))
+}
+
+/// 按字根数选用的单字取码规则
+#[derive(Debug, Clone, Deserialize)]
+pub struct 字源取码规则 {
+    #[serde(default)]
+    pub 最少字根数: usize,
+    /// 不填表示不限
+    pub 最多字根数: Option<usize>,
+    /// 依次取码，取不到的项跳过，取满最大码长为止
+    pub 取码: Vec<字源取码项>,
+}
+
+impl 字源取码规则 {
+    pub fn 适用(&self, 字根数: usize) -> bool {
+        字根数 >= self.最少字根数 && self.最多字根数.is_none_or(|x| 字根数 <= x)
+    }
+}
+
+/// 取码项中的序号从 0 开始，负数表示从末尾倒数，-1 为最后一个
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "类型")]
+pub enum 字源取码项 {
+    字根 {
+        序号: isize,
+    },
+    /// 字根的首笔补码
+    首笔 {
+        序号: isize,
+    },
+    /// 字根的第一笔、第二笔和末笔，笔画序号分别为 0、1、2
+    笔画 {
+        序号: isize,
+        笔画: usize,
+    },
+    /// 全拼中的字母，全拼不足最短长度时不取
+    拼音 {
+        序号: isize,
+        #[serde(default)]
+        最短长度: usize,
+    },
+    声母 {
+        #[serde(default)]
+        序号: isize,
+    },
+    韵母 {
+        #[serde(default)]
+        序号: isize,
+    },
+    /// 字形识别码：序号所指字根的末笔与整字字形组成的元素 `识别码-{末笔}-{字形}`，
+    /// 拆分输入中没有字形的字不取
+    识别码 {
+        序号: isize,
+    },
+}
+
+impl 字源取码项 {
+    pub fn 需要笔画(&self) -> bool {
+        matches!(
+            self,
+            字源取码项::首笔 { .. } | 字源取码项::笔画 { .. } | 字源取码项::识别码 { .. }
+        )
+    }
+}
+
+/// 简码空位的分配方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum 字源简码分配 {
+    /// 按频率顺序先到先得
+    #[default]
+    贪心,
+    /// 用最小费用流求全局最优，使总键数最少，比贪心慢得多
+    最优,
+}
+
+impl 字源编码配置 {
+    pub fn 简码阶梯(&self, 最大码长: usize) -> Vec<字源简码规则> {
+        if !self.简码规则.is_empty() {
+            return self.简码规则.clone();
+        }
+        // 二简只给全码比它长的字，加上空格后放不下的级别不出
+        let 前缀 = |长度: usize, 最短全码: usize| 字源简码规则 {
+            长度,
+            补空格: true,
+            最短全码,
+            ..Default::default()
+        };
+        let mut 阶梯 = match self.方案 {
+            字源方案::四码定长 => vec![前缀(1, 0), 前缀(2, 最大码长 - 1)],
+            字源方案::前缀 => vec![
+                字源简码规则 {
+                    类型: 字源简码类型::特简,
+                    特简: zip(特简字, 特简码)
+                        .map(|(字, 编码)| 字源特简项 {
+                            字,
+                            编码: 编码.to_string(),
+                        })
+                        .collect(),
+                    ..Default::default()
+                },
+                前缀(1, 0),
+                字源简码规则 {
+                    类型: 字源简码类型::笔画,
+                    长度: 2,
+                    ..Default::default()
+                },
+                字源简码规则 {
+                    空格计入编码: true,
+                    ..前缀(2, 最大码长)
+                },
+            ],
+        };
+        阶梯.retain(|规则| {
+            let 空格 = (规则.补空格 || 规则.空格计入编码) as usize;
+            规则.类型 == 字源简码类型::特简 || 规则.长度 + 空格 <= 最大码长
+        });
+        阶梯
+    }
+
+    pub fn 取码规则列表(&self, 最大码长: usize) -> Vec<字源取码规则> {
+        if !self.取码规则.is_empty() {
+            return self.取码规则.clone();
+        }
+        // 字根不足最大码长时用拼音或笔画补足，字根多于最大码长时取前几个和最后一个字根
+        let 字根 = |序号: isize| 字源取码项::字根 { 序号 };
+        let mut 规则列表 = vec![];
+        for 字根数 in 1..最大码长 {
+            let mut 取码: Vec<_> = (0..字根数 as isize).map(字根).collect();
+            match (self.方案, 字根数) {
+                (字源方案::四码定长, 1) => {
+                    取码.push(字源取码项::拼音 {
+                        序号: 0,
+                        最短长度: 0,
+                    });
+                    取码.push(字源取码项::拼音 {
+                        序号: -1,
+                        最短长度: 2,
+                    });
+                }
+                (字源方案::四码定长, _) => 取码.push(字源取码项::拼音 {
+                    序号: 0,
+                    最短长度: 0,
+                }),
+                (字源方案::前缀, 1) => {
+                    for 笔画 in 0..3 {
+                        取码.push(字源取码项::笔画 { 序号: 0, 笔画 });
+                    }
+                }
+                (字源方案::前缀, _) => 取码.push(字源取码项::首笔 {
+                    序号: 字根数 as isize - 1,
+                }),
+            }
+            规则列表.push(字源取码规则 {
+                最少字根数: 字根数,
+                最多字根数: Some(字根数),
+                取码,
+            });
+        }
+        let mut 取码: Vec<_> = (0..最大码长 as isize - 1).map(字根).collect();
+        取码.push(字根(-1));
+        规则列表.push(字源取码规则 {
+            最少字根数: 最大码长,
+            最多字根数: None,
+            取码,
+        });
+        规则列表
+    }
+
+    pub fn 词规则列表(&self, 最大码长: usize) -> Vec<字源词规则> {
+        if !self.词规则.is_empty() {
+            return self.词规则.clone();
+        }
+        // 二字词前字取一半（向上取整），后字取剩余码数；
+        // 三字及以上每字取第一码，字数多于最大码长时取前几字和末字
+        let 字母 = |序号: usize| (b'a' + 序号 as u8) as char;
+        let 前字码数 = 最大码长.div_ceil(2);
+        let mut 二字公式 = String::new();
+        for 序号 in 0..最大码长 {
+            let (字, 码) = if 序号 < 前字码数 {
+                ('A', 序号)
+            } else {
+                ('B', 序号 - 前字码数)
+            };
+            二字公式.push(字);
+            二字公式.push(字母(码));
+        }
+        let 首码 = |字数: usize| -> String {
+            (0..字数)
+                .map(|x| format!("{}a", 字母(x).to_ascii_uppercase()))
+                .collect()
+        };
+        let mut 规则列表 = vec![字源词规则 {
+            最少字数: 2,
+            最多字数: Some(2),
+            公式: 二字公式,
+        }];
+        for 字数 in 3..最大码长 {
+            规则列表.push(字源词规则 {
+                最少字数: 字数,
+                最多字数: Some(字数),
+                公式: 首码(字数),
+            });
+        }
+        规则列表.push(字源词规则 {
+            最少字数: 最大码长.max(3),
+            最多字数: None,
+            公式: 首码(最大码长 - 1) + "Za",
+        });
+        规则列表
+    }
+
+    pub fn 校验取码规则(&self) -> Vec<String> {
+        let mut 问题列表 = vec![];
+        for 规则 in &self.词规则 {
+            if let Err(原因) = 规则.解析() {
+                问题列表.push(原因);
+            }
+        }
+        // 没有适用规则的字词得到空序列，全都重在同一个编码上
+        if !self.取码规则.is_empty() {
+            let 范围: Vec<_> = self
+                .取码规则
+                .iter()
+                .map(|x| (x.最少字根数, x.最多字根数))
+                .collect();
+            if let Some(缺口) = 未覆盖数目(&范围, 1) {
+                问题列表.push(format!("取码规则没有覆盖字根数 {缺口}"));
+            }
+        }
+        if !self.词规则.is_empty() {
+            let 范围: Vec<_> = self
+                .词规则
+                .iter()
+                .map(|x| (x.最少字数, x.最多字数))
+                .collect();
+            if let Some(缺口) = 未覆盖数目(&范围, 2) {
+                问题列表.push(format!("词规则没有覆盖字数 {缺口}"));
+            }
+        }
+        for (序号, 规则) in self.取码规则.iter().enumerate() {
+            for 项 in &规则.取码 {
+                if let 字源取码项::笔画 { 笔画, .. } = 项 {
+                    if *笔画 > 2 {
+                        问题列表.push(format!(
+                            "取码规则第 {} 条：笔画序号只能是 0、1、2",
+                            序号 + 1
+                        ));
+                    }
+                }
+            }
+        }
+        问题列表
+    }
+
+    pub fn 校验简码规则(&self, 棱镜: &棱镜, 最大码长: usize) -> Vec<String> {
+        let mut 问题列表 = vec![];
+        for (序号, 规则) in self.简码规则.iter().enumerate() {
+            let 名称 = format!("简码规则第 {} 条", 序号 + 1);
+            if 规则.类型 == 字源简码类型::特简 {
+                for 项 in &规则.特简 {
+                    if 项.编码.is_empty() || 项.编码.chars().count() > 最大码长 {
+                        问题列表.push(format!("{名称}：{:?} 的特简码长度不合法", 项.字));
+                    }
+                    for 键 in 项.编码.chars() {
+                        if !棱镜.键转数字.contains_key(&键) {
+                            问题列表.push(format!("{名称}：特简码中的 {键:?} 不是按键"));
+                        }
+                    }
+                }
+                continue;
+            }
+            let 空格 = (规则.补空格 || 规则.空格计入编码) as usize;
+            if 规则.长度 == 0 || 规则.长度 + 空格 > 最大码长 {
+                问题列表.push(format!(
+                    "{名称}：加上空格后的码长必须在 1 到 {最大码长} 之间"
+                ));
+            }
+            if 规则.类型 == 字源简码类型::笔画 && 规则.长度 < 2 {
+                问题列表.push(format!("{名称}：笔画简码的长度至少为 2"));
+            }
+            if 规则.数量 == 0 {
+                问题列表.push(format!("{名称}：数量至少为 1"));
+            }
+        }
+        问题列表
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum 字源简码类型 {
+    /// 取全码的前若干码
+    #[default]
+    前缀,
+    /// 取全码的前若干码，最后一码换成对应字根首笔所在的键
+    笔画,
+    /// 按特简码表直接指定
+    特简,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum 字源简码适用 {
+    #[default]
+    一字,
+    多字,
+    全部,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct 字源特简项 {
+    pub 字: char,
+    pub 编码: String,
+}
+
+/// 简码阶梯中的一级
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct 字源简码规则 {
+    pub 类型: 字源简码类型,
+    /// 简码取全码的前几码，笔画简码的最后一码也计算在内
+    pub 长度: usize,
+    /// 每个简码最多容纳的候选数，第二个及以后的候选计为选重
+    pub 数量: u8,
+    /// 上屏时在简码后补一个空格
+    pub 补空格: bool,
+    /// 空格也计入简码本身，这样简码就不会占用同样前缀的编码
+    pub 空格计入编码: bool,
+    /// 全码至少有这么多码时才出这一级简码
+    pub 最短全码: usize,
+    pub 适用: 字源简码适用,
+    /// 仅用于特简类型；特简码不占用简码空间
+    pub 特简: Vec<字源特简项>,
+}
+
+impl Default for 字源简码规则 {
+    fn default() -> Self {
+        Self {
+            类型: 字源简码类型::前缀,
+            长度: 1,
+            数量: 1,
+            补空格: false,
+            空格计入编码: false,
+            最短全码: 0,
+            适用: 字源简码适用::一字,
+            特简: vec![],
+        }
+    }
+}
+
+/// 全码重码时各候选的排列顺序，决定哪些字词需要选重
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum 字源候选排序 {
+    /// 按频率降序
+    #[default]
+    频率,
+    /// 单字排在词语之前，同类之间按频率降序
+    一字优先,
+    /// 候选顺序文件中列出的字词按文件中的顺序排在最前，其余按频率降序
+    固定顺序,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct 字源优化配置 {
+    #[serde(default)]
+    pub objective: 字源目标权重,
+    #[serde(default)]
+    pub operator: 字源操作配置,
+    /// 不填时使用 metaheuristic 中配置的 chai 模拟退火
+    pub 求解器: Option<字源求解器配置>,
+    /// 目标函数平时只对编码变化的词增量更新，每隔这么多次全量重算一次以消除累计误差；
+    /// 为 0 或 1 时每次都全量计算
+    #[serde(default = "默认全量重算间隔")]
+    pub 全量重算间隔: usize,
+}
+
+fn 默认全量重算间隔() -> usize {
+    1000
+}
+
+impl Default for 字源优化配置 {
+    fn default() -> Self {
+        Self {
+            objective: 字源目标权重::default(),
+            operator: 字源操作配置::default(),
+            求解器: None,
+            全量重算间隔: 默认全量重算间隔(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct 字源操作配置 {
+    /// 抽样时安排的权重为 exp(打分偏好 × 打分)，为 0 时均匀抽样
+    pub 打分偏好: f64,
+    /// 各变异操作的相对概率
+    pub 产生字根: f64,
+    pub 湮灭字根: f64,
+    pub 移动字根: f64,
+    pub 交换字根: f64,
+    pub 轮换字根: f64,
+    /// 轮换字根时参与轮换的字根数
+    pub 轮换数量: usize,
+    /// 每次变异随机挑选字根的最多次数
+    pub 最大尝试次数: usize,
+    /// 根据各变异操作近期的接受率调整概率
+    pub 自适应: bool,
+    /// 接受率滑动平均的更新速度
+    pub 自适应学习率: f64,
+    /// 自适应时每个操作至少保留的概率
+    pub 最小概率: f64,
+}
+
+impl Default for 字源操作配置 {
+    fn default() -> Self {
+        Self {
+            打分偏好: 0.0,
+            产生字根: 0.2,
+            湮灭字根: 0.2,
+            移动字根: 0.6,
+            交换字根: 0.0,
+            轮换字根: 0.0,
+            轮换数量: 3,
+            最大尝试次数: 100,
+            自适应: false,
+            自适应学习率: 0.01,
+            最小概率: 0.05,
+        }
+    }
+}
+
+impl 字源操作配置 {
+    /// 最大打分为决策空间中安排打分的最大绝对值，用于检查抽样权重是否溢出
+    pub fn 校验(&self, 最大打分: f64) -> Vec<String> {
+        let mut 问题列表 = vec![];
+        let 相对概率 = [
+            ("产生字根", self.产生字根),
+            ("湮灭字根", self.湮灭字根),
+            ("移动字根", self.移动字根),
+            ("交换字根", self.交换字根),
+            ("轮换字根", self.轮换字根),
+        ];
+        for (名称, 概率) in 相对概率 {
+            if !概率.is_finite() || 概率 < 0.0 {
+                问题列表.push(format!("变异操作：{名称} 的相对概率必须是非负数"));
+            }
+        }
+        if 相对概率.iter().map(|x| x.1).sum::<f64>() <= 0.0 {
+            问题列表.push("变异操作：至少要有一个操作的相对概率大于 0".to_string());
+        }
+        // exp 的参数超过约 709 会溢出，过小则所有权重都为 0
+        if !self.打分偏好.is_finite() || self.打分偏好.abs() * 最大打分 > 700.0 {
+            问题列表.push(format!(
+                "变异操作：打分偏好 {} 与最大打分 {最大打分} 之积的绝对值不能超过 700",
+                self.打分偏好
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.自适应学习率) {
+            问题列表.push("变异操作：自适应学习率必须在 0 到 1 之间".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.最小概率) {
+            问题列表.push("变异操作：最小概率必须在 0 到 1 之间".to_string());
+        }
+        问题列表
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "算法")]
+pub enum 字源求解器配置 {
+    /// 使用 metaheuristic 中配置的 chai 模拟退火
+    退火,
+    延迟接受爬山(延迟接受参数),
+    禁忌搜索(禁忌搜索参数),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct 延迟接受参数 {
+    pub 步数: usize,
+    /// 和多少步之前的分数比较，为 1 时退化为普通爬山
+    #[serde(default = "默认延迟长度")]
+    pub 延迟长度: usize,
+}
+
+fn 默认延迟长度() -> usize {
+    1000
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct 禁忌搜索参数 {
+    pub 步数: usize,
+    /// 被移动过的元素在多少步内不能再次移动
+    #[serde(default = "默认禁忌长度")]
+    pub 禁忌长度: usize,
+    /// 每一步尝试的候选变异数量
+    #[serde(default = "默认邻域大小")]
+    pub 邻域大小: usize,
+}
+
+fn 默认禁忌长度() -> usize {
+    50
+}
+
+fn 默认邻域大小() -> usize {
+    20
+}
+
+/// 目标函数中各项指标的权重，未给出的项使用默认值
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct 字源目标权重 {
+    pub 一字全码选重率: f64,
+    pub 一字全码静态选重率: f64,
+    pub 一字简码选重率: f64,
+    pub 一字简码静态选重率: f64,
+    pub 多字全码选重率: f64,
+    pub 多字全码静态选重率: f64,
+    pub 组合当量: f64,
+    pub 按键分布偏差: f64,
+    pub 一字简码码长: f64,
+    pub 字根数: f64,
+    /// 所选安排的打分低于该元素最高打分的部分之和
+    pub 打分损失: f64,
+    /// 按频率分级的一字指标权重，为空时使用默认分级且不计入目标函数
+    pub 分级: Vec<字源分级权重>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct 字源分级权重 {
+    /// 频率最高的前若干个字，不填则为全部单字
+    pub 前: Option<usize>,
+    pub 选重率: f64,
+    pub 码长: f64,
+    pub 组合当量: f64,
+}
+
+impl Default for 字源目标权重 {
+    fn default() -> Self {
+        Self {
+            一字全码选重率: 1.0,
+            一字全码静态选重率: 0.3,
+            一字简码选重率: 0.5,
+            一字简码静态选重率: 0.1,
+            多字全码选重率: 0.3,
+            多字全码静态选重率: 0.03,
+            组合当量: 0.5,
+            按键分布偏差: 0.01,
+            一字简码码长: 0.01,
+            字根数: 0.00003,
+            打分损失: 0.0,
+            分级: vec![],
+        }
+    }
+}
+
+impl 字源配置 {
+    pub fn 读取(路径: &PathBuf) -> Result<Self, 错误> {
+        let 内容 = read_to_string(路径)
+            .map_err(|e| 错误::from(format!("无法读取配置文件 {路径:?}：{e}")))?;
+        let 格式错误 = |e| 错误::from(format!("配置文件 {路径:?} 格式错误：{e}"));
+        let mut 字源配置: Self = from_str(&内容).map_err(格式错误)?;
+        字源配置.原始内容 = from_str(&内容).map_err(格式错误)?;
+        Ok(字源配置)
+    }
+}
@@ -1,45 +1,84 @@
+use crate::config::{字源候选排序, 字源取码项, 字源简码类型, 字源配置};
 use chai::{
     config::{Condition, Mapped, MappedKey, 配置},
     contexts::{上下文, 合并初始决策, 展开变量, 拓扑排序},
     interfaces::默认输入,
     objectives::metric::指法标记,
     optimizers::决策,
-    元素, 原始当量信息, 原始键位分布信息, 棱镜, 码表项, 编码信息, 错误,
+    元素, 原始当量信息, 原始键位分布信息, 棱镜, 码表项, 编码, 编码信息, 错误,
 };
 use chrono::Local;
-use core::panic;
 use indexmap::IndexMap;
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
-use serde_yaml::{from_str, to_string};
+use serde_yaml::{Value, from_str, to_string, to_value};
 use std::{
     fs::{File, read_to_string},
     io::Write,
     path::PathBuf,
 };
 
-#[derive(PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum 字源方案 {
+    #[default]
     四码定长,
     前缀,
 }
-pub const 方案: 字源方案 = 字源方案::四码定长;
 // pub const 大集合: [char; 21] = [
 //     'b', 'p', 'm', 'f', 'd', 't', 'n', 'l', 'g', 'k', 'h', 'j', 'q', 'x', 'z', 'c', 's', 'r', 'w',
 //     'y', 'v',
 // ];
 // pub const 小集合: [char; 5] = ['a', 'e', 'i', 'o', 'u'];
-pub const 字母表: [char; 27] = [
-    'b', 'p', 'm', 'f', 'd', 't', 'n', 'l', 'g', 'k', 'h', 'j', 'q', 'x', 'z', 'c', 's', 'r', 'w',
-    'y', 'v', 'a', 'e', 'i', 'o', 'u', '_',
-];
 pub const 特简码: [char; 5] = ['a', 'e', 'i', 'o', 'u'];
 pub const 特简字: [char; 5] = ['了', '的', '是', '我', '不'];
-pub const 最大码长: u64 = 4;
-pub const 进制: u64 = 28;
-pub const 空格: u64 = 27;
+/// 元素序列的长度，配置中的最大码长不能超过这个值
+pub const 最大码长上限: usize = 6;
+/// 编码空间过大时全码、简码和当量数组会占用过多内存
+const 最大编码空间: u64 = 1 << 27;
 pub type 频率 = u64;
 
+/// 由字母表和最大码长决定的编码参数。字母表中的 '_' 表示空格，
+/// 编码按 进制 进位，第一码在最低位
+#[derive(Debug, Clone, Copy)]
+pub struct 码制 {
+    pub 进制: u64,
+    pub 空格: u64,
+    pub 最大码长: usize,
+}
+
+impl 码制 {
+    pub fn 编码空间大小(&self) -> usize {
+        self.进制.pow(self.最大码长 as u32) as usize
+    }
+
+    pub fn 码长(&self, 编码: 编码) -> usize {
+        let mut 码长 = 0;
+        while 码长 < self.最大码长 && 编码 >= self.进制.pow(码长 as u32) {
+            码长 += 1;
+        }
+        码长
+    }
+
+    /// 不足最大码长的编码在末尾补一个空格
+    pub fn 补空格(&self, 编码: 编码) -> 编码 {
+        let 码长 = self.码长(编码);
+        if 码长 < self.最大码长 {
+            编码 + self.空格 * self.进制.pow(码长 as u32)
+        } else {
+            编码
+        }
+    }
+
+    pub fn 全码(
+        &self, 元素序列: &[元素; 最大码长上限], 映射: &线性化决策
+    ) -> 编码 {
+        元素序列[..self.最大码长]
+            .iter()
+            .rev()
+            .fold(0, |编码, 元素| 编码 * self.进制 + 映射[*元素])
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(tag = "类型", rename_all = "snake_case")]
 pub enum 字源元素安排 {
@@ -49,19 +88,21 @@ pub enum 字源元素安排 {
 }
 
 impl 字源元素安排 {
-    fn from(mapped: &Mapped, 棱镜: &棱镜) -> Self {
+    fn from(mapped: &Mapped, 棱镜: &棱镜) -> Result<Self, String> {
         match mapped {
-            Mapped::Unused(()) => 字源元素安排::未选取,
-            Mapped::Grouped { element } => 字源元素安排::归并(棱镜.元素转数字[element]),
-            Mapped::Basic(keys) => 字源元素安排::键位(
-                keys.chars().next().expect("Basic 映射应至少包含一个字符"),
-            ),
-            Mapped::Advanced(keys) => {
-                let MappedKey::Ascii(k) = &keys[0] else {
-                    panic!("无法从高级映射中恢复元素安排: {:?}", mapped);
-                };
-                字源元素安排::键位(*k)
-            }
+            Mapped::Unused(()) => Ok(字源元素安排::未选取),
+            Mapped::Grouped { element } => match 棱镜.元素转数字.get(element) {
+                Some(字根) => Ok(字源元素安排::归并(*字根)),
+                None => Err(format!("归并到不存在的元素 {element:?}")),
+            },
+            Mapped::Basic(keys) => match keys.chars().next() {
+                Some(键位) => Ok(字源元素安排::键位(键位)),
+                None => Err("Basic 映射应至少包含一个字符".to_string()),
+            },
+            Mapped::Advanced(keys) => match keys.first() {
+                Some(MappedKey::Ascii(k)) => Ok(字源元素安排::键位(*k)),
+                _ => Err(format!("无法从高级映射中恢复元素安排: {mapped:?}")),
+            },
         }
     }
 
@@ -79,7 +120,9 @@ impl 字源元素安排 {
 #[derive(Clone)]
 pub struct 字源上下文 {
     pub 配置: 配置,
+    pub 字源配置: 字源配置,
     pub 棱镜: 棱镜,
+    pub 码制: 码制,
     pub 初始决策: 字源决策,
     pub 决策空间: 字源决策空间,
     pub 原始键位分布信息: 原始键位分布信息,
@@ -91,7 +134,12 @@ pub struct 字源上下文 {
     pub 数字转块: FxHashMap<usize, String>,
     pub 字根首笔: Vec<元素>,
     pub 字根笔画: Vec<(元素, 元素, 元素)>,
+    /// 字根末笔的笔画类别 1 到 5，没有笔画数据时为 0
+    pub 字根末笔: Vec<u8>,
     pub 元素图: FxHashMap<元素, Vec<元素>>,
+    pub 跳过多字词: Vec<String>,
+    /// 字词 -> 在固定候选顺序中的位置
+    pub 固定顺序: FxHashMap<String, usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -118,7 +166,7 @@ pub type 线性化决策 = Vec<u64>;
 impl 字源决策 {
     pub fn 线性化(&self, 棱镜: &棱镜) -> 线性化决策 {
         let mut 映射 = vec![0; self.元素.len()];
-        for k in 0..进制 as usize {
+        for k in 0..棱镜.进制 as usize {
             映射[k] = k as u64;
         }
         for (元素, 安排) in self.元素.iter().enumerate() {
@@ -220,10 +268,8 @@ impl 决策 for 字源决策 {
 impl 上下文 for 字源上下文 {
     type 决策 = 字源决策;
 
+    /// 只替换原始配置文件中的 form.mapping 和 info.version，字源扩展字段原样保留
     fn 序列化(&self, 解: &字源决策) -> String {
-        let mut 新配置 = self.配置.clone();
-        新配置.info.as_mut().unwrap().version =
-            Some(format!("{}", Local::now().format("%Y-%m-%d+%H:%M:%S")));
         let mut mapping = IndexMap::new();
         for (元素, 安排) in 解.元素.iter().enumerate() {
             let mapped: Mapped = 安排.to_mapped(&self.棱镜);
@@ -231,7 +277,14 @@ impl 上下文 for 字源上下文 {
                 mapping.insert(self.棱镜.数字转元素[&元素].clone(), mapped);
             }
         }
-        新配置.form.mapping = mapping;
+        let mut 新配置 = if self.字源配置.原始内容.is_mapping() {
+            self.字源配置.原始内容.clone()
+        } else {
+            to_value(&self.配置).unwrap()
+        };
+        新配置["info"]["version"] =
+            Value::from(format!("{}", Local::now().format("%Y-%m-%d+%H:%M:%S")));
+        新配置["form"]["mapping"] = to_value(&mapping).unwrap();
         to_string(&新配置).unwrap()
     }
 }
@@ -250,6 +303,9 @@ struct 原始汉字信息 {
     频率: u64, // 已移至读音
     读音: Vec<原始读音>,
     字块: Vec<String>,
+    /// 左右、上下、独体等整字结构，只有用到识别码时才需要
+    #[serde(default)]
+    字形: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -267,14 +323,19 @@ struct 拆分输入 {
 }
 
 pub type 块 = usize;
-pub type 动态拆分项 = Vec<[元素; 4]>;
+pub type 动态拆分项 = Vec<Vec<元素>>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct 一字信息项 {
     pub 词: char,
     pub 频率: 频率,
-    pub 字块: [块; 4],
-    pub 全拼顺取: [usize; 3],
+    pub 字块: Vec<块>,
+    /// 全拼各字母对应的键，不含声调
+    pub 拼音: Vec<元素>,
+    /// 全拼的前几个字母是声母，其余是韵母
+    pub 声母长度: usize,
+    /// 末笔为 1 到 5 时对应的识别码元素，没有字形时为空
+    pub 识别码: Option<[元素; 5]>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -283,24 +344,26 @@ pub struct 多字信息项 {
     pub 频率: 频率,
 }
 
-pub fn 对齐(列表: Vec<元素>, 默认值: 元素) -> [元素; 4] {
-    [0, 1, 2, 3].map(|i| {
-        if i == 3 && 列表.len() > 3 {
-            列表[列表.len() - 1]
-        } else if i < 列表.len() {
-            列表[i]
-        } else {
-            默认值
-        }
-    })
-}
-
 impl 字源上下文 {
-    pub fn 新建(输入: 默认输入) -> Result<Self, 错误> {
+    pub fn 新建(输入: 默认输入, 字源配置: 字源配置) -> Result<Self, 错误> {
         let 布局 = 输入.配置.form.clone();
         let mut 原始决策 = 布局.mapping;
-        let mut 原始决策空间 = 布局.mapping_space.unwrap();
-        let 原始变量映射 = 布局.mapping_variables.unwrap();
+        let mut 配置问题 = vec![];
+        if 布局.mapping_space.is_none() {
+            配置问题.push("form：缺少 mapping_space".to_string());
+        }
+        if 布局.mapping_variables.is_none() {
+            配置问题.push("form：缺少 mapping_variables".to_string());
+        }
+        let (Some(mut 原始决策空间), Some(原始变量映射)) =
+            (布局.mapping_space, 布局.mapping_variables)
+        else {
+            return Err(错误::from(format!(
+                "配置文件中有 {} 处问题：\n{}",
+                配置问题.len(),
+                配置问题.join("\n")
+            )));
+        };
         let mut 元素转数字 = FxHashMap::default();
         let mut 数字转元素 = FxHashMap::default();
         let mut 键转数字 = FxHashMap::default();
@@ -312,7 +375,33 @@ impl 字源上下文 {
         }
         合并初始决策(&mut 原始决策空间, &mut 原始决策);
         展开变量(&mut 原始决策空间, &原始变量映射);
-        let (所有元素, 原始元素图) = 拓扑排序(&原始决策空间).unwrap();
+        let (所有元素, 原始元素图) = 拓扑排序(&原始决策空间)?;
+        let mut 字母表: Vec<char> = 布局.alphabet.chars().collect();
+        if !字母表.contains(&'_') {
+            字母表.push('_');
+        }
+        let 进制 = 字母表.len() as u64 + 1;
+        let 码制 = 码制 {
+            进制,
+            空格: 字母表.iter().position(|x| *x == '_').unwrap() as u64 + 1,
+            最大码长: 输入.配置.encoder.max_length,
+        };
+        if 码制.最大码长 == 0 || 码制.最大码长 > 最大码长上限 {
+            return Err(错误::from(format!(
+                "最大码长 {} 不在 1 到 {最大码长上限} 之间",
+                码制.最大码长
+            )));
+        }
+        if 进制
+            .checked_pow(码制.最大码长 as u32)
+            .is_none_or(|x| x > 最大编码空间)
+        {
+            return Err(错误::from(format!(
+                "字母表有 {} 个键，最大码长为 {}，编码空间过大",
+                字母表.len(),
+                码制.最大码长
+            )));
+        }
         let mut 序号 = 0;
         for c in 字母表 {
             序号 += 1;
@@ -331,7 +420,7 @@ impl 字源上下文 {
             数字转键,
             元素转数字,
             数字转元素,
-            进制: 进制 as u64,
+            进制,
         };
 
         let 最大数量 = 棱镜.数字转元素.len() + 1;
@@ -342,6 +431,7 @@ impl 字源上下文 {
         let mut 初始决策 = 字源决策 {
             元素: vec![字源元素安排::未选取; 最大数量],
         };
+        let mut 问题列表 = vec![];
         for 元素名称 in &所有元素 {
             let 序号 = 棱镜.元素转数字[元素名称];
             if 元素名称.chars().count() == 1 {
@@ -351,7 +441,13 @@ impl 字源上下文 {
             let 原始安排 = 原始决策[元素名称].clone();
             let mut 安排列表 = vec![];
             for 可行原始安排 in &原始安排列表 {
-                let 可行安排 = 字源元素安排::from(&可行原始安排.value, &棱镜);
+                let 可行安排 = match 字源元素安排::from(&可行原始安排.value, &棱镜) {
+                    Ok(安排) => 安排,
+                    Err(原因) => {
+                        问题列表.push(format!("决策空间：元素 {元素名称:?} 的安排{原因}"));
+                        continue;
+                    }
+                };
                 let mut 原始条件 = 可行原始安排.condition.clone().unwrap_or_default();
                 if let 字源元素安排::归并(字根) = &可行安排 {
                     let 默认条件 = Condition {
@@ -363,14 +459,33 @@ impl 字源上下文 {
                         原始条件.push(默认条件);
                     }
                 }
-                let 条件列表: Vec<条件> = 原始条件
-                    .into_iter()
-                    .map(|c| 条件 {
-                        元素: 棱镜.元素转数字[&c.element],
-                        谓词: c.op == "是",
-                        值: 字源元素安排::from(&c.value, &棱镜),
-                    })
-                    .collect();
+                let mut 条件列表 = vec![];
+                for c in 原始条件 {
+                    let Some(&条件元素) = 棱镜.元素转数字.get(&c.element) else {
+                        问题列表.push(format!(
+                            "决策空间：元素 {元素名称:?} 的条件引用了不存在的元素 {:?}",
+                            c.element
+                        ));
+                        continue;
+                    };
+                    if c.op != "是" && c.op != "不是" {
+                        问题列表.push(format!(
+                            "决策空间：元素 {元素名称:?} 的条件使用了未知的谓词 {:?}",
+                            c.op
+                        ));
+                        continue;
+                    }
+                    match 字源元素安排::from(&c.value, &棱镜) {
+                        Ok(值) => 条件列表.push(条件 {
+                            元素: 条件元素,
+                            谓词: c.op == "是",
+                            值,
+                        }),
+                        Err(原因) => {
+                            问题列表.push(format!("决策空间：元素 {元素名称:?} 的条件{原因}"))
+                        }
+                    }
+                }
                 let 条件字根安排 = 字源条件元素安排 {
                     安排: 可行安排,
                     条件列表,
@@ -378,9 +493,28 @@ impl 字源上下文 {
                 };
                 安排列表.push(条件字根安排);
             }
-            初始决策.元素[序号] = 字源元素安排::from(&原始安排, &棱镜);
+            match 字源元素安排::from(&原始安排, &棱镜) {
+                Ok(安排) => 初始决策.元素[序号] = 安排,
+                Err(原因) => 问题列表.push(format!("初始决策：元素 {元素名称:?} {原因}")),
+            }
             决策空间.元素[序号] = 安排列表;
         }
+        问题列表.extend(字源配置.encoder.校验简码规则(&棱镜, 码制.最大码长));
+        问题列表.extend(字源配置.encoder.校验取码规则());
+        let 最大打分 = 决策空间
+            .元素
+            .iter()
+            .flatten()
+            .map(|x| x.打分.abs())
+            .fold(0.0, f64::max);
+        问题列表.extend(字源配置.optimization.operator.校验(最大打分));
+        if !问题列表.is_empty() {
+            return Err(错误::from(format!(
+                "配置文件中有 {} 处问题：\n{}",
+                问题列表.len(),
+                问题列表.join("\n")
+            )));
+        }
 
         let mut 元素图 = FxHashMap::default();
         for (元素名称, 下游名称列表) in 原始元素图 {
@@ -391,12 +525,24 @@ impl 字源上下文 {
                 .collect();
             元素图.insert(元素, 下游元素列表);
         }
-        let (一字信息, 多字信息, 动态拆分, 块转数字, 数字转块, 字根首笔, 字根笔画) =
-            Self::解析动态拆分(&棱镜, &决策空间);
+        let (
+            一字信息,
+            多字信息,
+            动态拆分,
+            块转数字,
+            数字转块,
+            字根首笔,
+            字根笔画,
+            字根末笔,
+            跳过多字词,
+        ) = Self::解析动态拆分(&棱镜, &决策空间, &字源配置, &码制)?;
+        let 固定顺序 = Self::读取固定顺序(&字源配置)?;
 
         Ok(Self {
             配置: 输入.配置,
+            字源配置,
             棱镜,
+            码制,
             初始决策,
             决策空间,
             原始键位分布信息: 输入.原始键位分布信息,
@@ -408,47 +554,115 @@ impl 字源上下文 {
             数字转块,
             字根首笔,
             字根笔画,
+            字根末笔,
             元素图,
+            跳过多字词,
+            固定顺序,
         })
     }
 
+    /// 只有候选排序为固定顺序时才需要候选顺序文件
+    fn 读取固定顺序(字源配置: &字源配置) -> Result<FxHashMap<String, usize>, 错误> {
+        let mut 固定顺序 = FxHashMap::default();
+        if 字源配置.encoder.候选排序 != 字源候选排序::固定顺序 {
+            return Ok(固定顺序);
+        }
+        let Some(路径) = &字源配置.data.候选顺序 else {
+            return Err(错误::from(
+                "候选排序为固定顺序时需要在 data.候选顺序 中指定候选顺序文件".to_string(),
+            ));
+        };
+        let 内容 = read_to_string(路径)
+            .map_err(|e| 错误::from(format!("无法读取候选顺序文件 {路径:?}：{e}")))?;
+        for 词 in 内容.lines().filter_map(|x| x.split_whitespace().next()) {
+            let 位置 = 固定顺序.len();
+            固定顺序.entry(词.to_string()).or_insert(位置);
+        }
+        Ok(固定顺序)
+    }
+
     pub fn 解析动态拆分(
         棱镜: &棱镜,
         决策空间: &字源决策空间,
-    ) -> (
-        Vec<一字信息项>,
-        Vec<多字信息项>,
-        Vec<动态拆分项>,
-        FxHashMap<String, usize>,
-        FxHashMap<usize, String>,
-        Vec<元素>,
-        Vec<(元素, 元素, 元素)>,
-    ) {
-        let 拆分输入: 拆分输入 =
-            from_str(&read_to_string("dynamic_analysis.yaml").unwrap()).unwrap();
+        字源配置: &字源配置,
+        码制: &码制,
+    ) -> Result<
+        (
+            Vec<一字信息项>,
+            Vec<多字信息项>,
+            Vec<动态拆分项>,
+            FxHashMap<String, usize>,
+            FxHashMap<usize, String>,
+            Vec<元素>,
+            Vec<(元素, 元素, 元素)>,
+            Vec<u8>,
+            Vec<String>,
+        ),
+        错误,
+    > {
+        // 取码规则或简码阶梯用到笔画时才需要字根笔画数据
+        let 取码规则列表 = 字源配置.encoder.取码规则列表(码制.最大码长);
+        let 需要笔画 = 取码规则列表
+            .iter()
+            .any(|x| x.取码.iter().any(|y| y.需要笔画()))
+            || 字源配置
+                .encoder
+                .简码阶梯(码制.最大码长)
+                .iter()
+                .any(|x| x.类型 == 字源简码类型::笔画);
+        let 需要识别码 = 取码规则列表.iter().any(|x| {
+            x.取码
+                .iter()
+                .any(|y| matches!(y, 字源取码项::识别码 { .. }))
+        });
+        let 路径 = &字源配置.data.动态拆分;
+        let 内容 = read_to_string(路径)
+            .map_err(|e| 错误::from(format!("无法读取动态拆分文件 {路径:?}：{e}")))?;
+        let 拆分输入: 拆分输入 = from_str(&内容)
+            .map_err(|e| 错误::from(format!("动态拆分文件 {路径:?} 格式错误：{e}")))?;
+        // 收集全部数据问题，一次性报告
+        let mut 问题列表 = vec![];
         let mut 动态拆分 = vec![];
         let mut 块转数字 = FxHashMap::default();
         let mut 数字转块 = FxHashMap::default();
         let mut 字根首笔 = vec![0; 决策空间.元素.len()];
         let mut 字根笔画 = vec![(0, 0, 0); 决策空间.元素.len()];
-        if 方案 == 字源方案::前缀 {
+        let mut 字根末笔 = vec![0; 决策空间.元素.len()];
+        let mut 有笔画的字根 = FxHashSet::default();
+        if 需要笔画 {
+            let 查找笔画 = |笔画: String, 问题列表: &mut Vec<String>| {
+                if let Some(序号) = 棱镜.元素转数字.get(&笔画) {
+                    *序号
+                } else {
+                    问题列表.push(format!("字根笔画：笔画元素 {笔画:?} 不在棱镜中"));
+                    0
+                }
+            };
             for (字根, 笔画列表) in &拆分输入.字根笔画 {
+                let Some(&字根序号) = 棱镜.元素转数字.get(字根) else {
+                    问题列表.push(format!("字根笔画：字根 {字根:?} 不在棱镜中"));
+                    continue;
+                };
+                if 笔画列表.is_empty() {
+                    问题列表.push(format!("字根笔画：字根 {字根:?} 的笔画列表为空"));
+                    continue;
+                }
+                有笔画的字根.insert(字根序号);
                 let 小集合笔画 = format!("补码-{}", 笔画列表[0].min(5));
-                let 笔画序号 = 棱镜.元素转数字[&小集合笔画];
-                let 字根序号 = 棱镜.元素转数字[字根];
-                字根首笔[字根序号] = 笔画序号;
-                let 第一笔 = 棱镜.元素转数字[&笔画列表[0].to_string()];
+                字根首笔[字根序号] = 查找笔画(小集合笔画, &mut 问题列表);
+                let 第一笔 = 查找笔画(笔画列表[0].to_string(), &mut 问题列表);
                 let 第二笔 = if 笔画列表.len() > 1 {
-                    棱镜.元素转数字[&笔画列表[1].to_string()]
+                    查找笔画(笔画列表[1].to_string(), &mut 问题列表)
                 } else {
                     0
                 };
                 let 末笔 = if 笔画列表.len() > 2 {
-                    棱镜.元素转数字[&笔画列表[笔画列表.len() - 1].to_string()]
+                    查找笔画(笔画列表[笔画列表.len() - 1].to_string(), &mut 问题列表)
                 } else {
                     0
                 };
                 字根笔画[字根序号] = (第一笔, 第二笔, 末笔);
+                字根末笔[字根序号] = 笔画列表[笔画列表.len() - 1].min(5);
             }
         }
         for (块, 原始拆分方式列表) in 拆分输入.动态拆分 {
@@ -457,69 +671,180 @@ impl 字源上下文 {
             数字转块.insert(块序号, 块.clone());
             let mut 拆分方式列表 = vec![];
             for 原始拆分方式 in &原始拆分方式列表 {
-                for 拆分方式 in 原始拆分方式 {
-                    assert!(
-                        棱镜.元素转数字.contains_key(拆分方式),
-                        "元素 {} 不在棱镜中",
-                        拆分方式
-                    );
+                let mut 字根列表 = vec![];
+                for 字根 in 原始拆分方式 {
+                    if let Some(&字根序号) = 棱镜.元素转数字.get(字根) {
+                        if 需要笔画 && !有笔画的字根.contains(&字根序号) {
+                            问题列表.push(format!(
+                                "动态拆分：块 {块:?} 中的字根 {字根:?} 缺少字根笔画"
+                            ));
+                        }
+                        字根列表.push(字根序号);
+                    } else {
+                        问题列表.push(format!("动态拆分：块 {块:?} 中的字根 {字根:?} 不在棱镜中"));
+                    }
                 }
-                let 拆分方式 = 对齐(
-                    原始拆分方式
-                        .iter()
-                        .map(|字根| 棱镜.元素转数字[字根])
-                        .collect(),
-                    0,
-                );
-                拆分方式列表.push(拆分方式);
+                拆分方式列表.push(字根列表);
             }
             // 检查原始拆分方式列表的最后一项都是必选字根
-            let 最后一项 = 原始拆分方式列表.last().unwrap();
-            if !最后一项.iter().all(|x| {
-                !决策空间.元素[棱镜.元素转数字[x]]
-                    .iter()
-                    .any(|x| x.安排 == 字源元素安排::未选取)
-            }) {
-                panic!("动态拆分方式列表的最后一项必须都是必选字根, {块:?}, {原始拆分方式列表:?}");
+            match 原始拆分方式列表.last() {
+                None => 问题列表.push(format!("动态拆分：块 {块:?} 没有任何拆分方式")),
+                Some(最后一项) => {
+                    let 非必选字根: Vec<_> = 最后一项
+                        .iter()
+                        .filter(|x| {
+                            棱镜.元素转数字.get(*x).is_some_and(|&序号| {
+                                决策空间.元素[序号]
+                                    .iter()
+                                    .any(|x| x.安排 == 字源元素安排::未选取)
+                            })
+                        })
+                        .collect();
+                    if !非必选字根.is_empty() {
+                        问题列表.push(format!(
+                            "动态拆分：块 {块:?} 的最后一种拆分方式 {最后一项:?} 中 {非必选字根:?} 不是必选字根"
+                        ));
+                    }
+                }
             }
             动态拆分.push(拆分方式列表);
         }
         let mut 一字信息 = vec![];
         let mut 多字信息 = vec![];
         let mut 合法汉字 = FxHashSet::default();
+        let mut 全部汉字 = FxHashSet::default();
+        let mut 跳过多字词 = vec![];
+        let 数据配置 = &字源配置.data;
+        let 包含: FxHashSet<char> = 数据配置
+            .包含
+            .chars()
+            .filter(|x| !x.is_whitespace())
+            .collect();
+        let 排除: FxHashSet<char> = 数据配置
+            .排除
+            .chars()
+            .filter(|x| !x.is_whitespace())
+            .collect();
         for 词 in &拆分输入.汉字信息 {
-            if !(词.gb2312 > 0 && 词.通规 > 0) {
+            全部汉字.insert(词.汉字);
+            let 在字集中 = 数据配置.字集.包含(词.gb2312 > 0, 词.通规 > 0);
+            if !(在字集中 || 包含.contains(&词.汉字)) || 排除.contains(&词.汉字) {
                 continue;
             }
             合法汉字.insert(词.汉字);
-            let 字块 = 对齐(词.字块.iter().map(|块| 块转数字[块]).collect(), usize::MAX);
-            let 拼音 = 词.读音.iter().max_by_key(|x| x.频率).cloned().unwrap().拼音;
-            let mut 拼音字符: Vec<char> = 拼音.chars().collect();
-            拼音字符.pop(); // 去掉声调
-            let mut 全拼顺取 = [0; 3];
-            if !拼音字符.is_empty() {
-                // 第一码：拼音第一个字符
-                全拼顺取[0] = 棱镜.键转数字[&拼音字符[0]] as 元素;
-                // 第二码：如果有第二个字符就取，否则取第一个
-                if 拼音字符.len() > 1 {
-                    全拼顺取[1] = 棱镜.键转数字[&拼音字符[1]] as 元素;
+            let mut 块列表 = vec![];
+            for 块 in &词.字块 {
+                if let Some(&块序号) = 块转数字.get(块) {
+                    块列表.push(块序号);
                 } else {
-                    全拼顺取[1] = 全拼顺取[0];
+                    问题列表.push(format!(
+                        "汉字信息：{} 的字块 {块:?} 不在动态拆分中",
+                        词.汉字
+                    ));
                 }
-                // 第三码：拼音最后一个字符
-                全拼顺取[2] = 棱镜.键转数字[&拼音字符[拼音字符.len() - 1]] as 元素;
             }
-            一字信息.push(一字信息项 {
-                词: 词.汉字,
-                频率: 词.频率 as 频率,
-                字块,
-                全拼顺取,
-            });
+            let 字块 = 块列表;
+            let mut 识别码 = None;
+            if let Some(字形) = 词.字形.as_ref().filter(|_| 需要识别码) {
+                let mut 识别码元素 = [0; 5];
+                for (末笔, 识别码项) in 识别码元素.iter_mut().enumerate() {
+                    let 名称 = format!("识别码-{}-{字形}", 末笔 + 1);
+                    if let Some(&序号) = 棱镜.元素转数字.get(&名称) {
+                        *识别码项 = 序号;
+                    } else {
+                        问题列表.push(format!(
+                            "汉字信息：{} 的识别码元素 {名称:?} 不在棱镜中",
+                            词.汉字
+                        ));
+                    }
+                }
+                识别码 = Some(识别码元素);
+            }
+            let Some(主要读音) = 词.读音.iter().max_by_key(|x| x.频率) else {
+                问题列表.push(format!("汉字信息：{} 没有读音", 词.汉字));
+                continue;
+            };
+            // 多音字模式下每个读音各成一项，使用读音自己的频率；
+            // 声调不同而字母相同的读音合并为一项
+            let 读音列表: Vec<(String, 频率)> = if 字源配置.data.多音字 {
+                let mut 合并读音: Vec<(String, 频率)> = vec![];
+                for 读音 in &词.读音 {
+                    let mut 字母: Vec<char> = 读音.拼音.chars().collect();
+                    字母.pop(); // 去掉声调
+                    let 字母: String = 字母.into_iter().collect();
+                    match 合并读音.iter_mut().find(|x| x.0 == 字母) {
+                        Some(已有) => 已有.1 += 读音.频率,
+                        None => 合并读音.push((字母, 读音.频率)),
+                    }
+                }
+                合并读音.retain(|x| x.1 > 0);
+                if 合并读音.is_empty() {
+                    let mut 字母 = 主要读音.拼音.clone();
+                    字母.pop();
+                    合并读音.push((字母, 词.频率));
+                }
+                合并读音
+            } else {
+                let mut 字母 = 主要读音.拼音.clone();
+                字母.pop(); // 去掉声调
+                vec![(字母, 词.频率)]
+            };
+            for (拼音, 读音频率) in 读音列表 {
+                let 拼音字符: Vec<char> = 拼音.chars().collect();
+                let mut 拼音元素 = vec![];
+                for 字符 in &拼音字符 {
+                    if let Some(&键) = 棱镜.键转数字.get(字符) {
+                        拼音元素.push(键 as 元素);
+                    } else {
+                        问题列表.push(format!(
+                            "汉字信息：{} 的读音 {拼音:?} 中的字母 {字符:?} 不在字母表中",
+                            词.汉字
+                        ));
+                    }
+                }
+                let 拼音前缀: String = 拼音字符.iter().take(2).collect();
+                let 声母长度 = if ["zh", "ch", "sh"].contains(&拼音前缀.as_str()) {
+                    2
+                } else if 拼音字符
+                    .first()
+                    .is_some_and(|x| "bpmfdtnlgkhjqxrzcsyw".contains(*x))
+                {
+                    1
+                } else {
+                    0
+                };
+                一字信息.push(一字信息项 {
+                    词: 词.汉字,
+                    频率: 读音频率,
+                    字块: 字块.clone(),
+                    拼音: 拼音元素,
+                    声母长度,
+                    识别码,
+                });
+            }
+        }
+        for 字 in &包含 {
+            if !全部汉字.contains(字) {
+                问题列表.push(format!("字集：包含的字 {字} 不在汉字信息中"));
+            }
+        }
+        if !问题列表.is_empty() {
+            return Err(错误::from(format!(
+                "动态拆分文件 {路径:?} 中有 {} 处问题：\n{}",
+                问题列表.len(),
+                问题列表.join("\n")
+            )));
         }
         一字信息.sort_by(|a, b| b.频率.partial_cmp(&a.频率).unwrap());
+        // 含有汉字信息中没有的字属于数据问题，留给校验报告；字集外的字只计数
+        let mut 字集外多字词数 = 0;
         for 词 in &拆分输入.多字词信息 {
+            if 词.词.chars().any(|c| !全部汉字.contains(&c)) {
+                跳过多字词.push(词.词.clone());
+                continue;
+            }
             if 词.词.chars().any(|c| !合法汉字.contains(&c)) {
-                println!("跳过多字词: {}", 词.词);
+                字集外多字词数 += 1;
                 continue;
             }
             多字信息.push(多字信息项 {
@@ -528,7 +853,14 @@ impl 字源上下文 {
             });
         }
         多字信息.sort_by(|a, b| b.频率.partial_cmp(&a.频率).unwrap());
-        (
+        let 字集外汉字数 = 全部汉字.len() - 合法汉字.len();
+        if 字集外汉字数 + 字集外多字词数 + 跳过多字词.len() > 0 {
+            println!(
+                "跳过字集外汉字 {字集外汉字数} 个、字集外多字词 {字集外多字词数} 个、含未知汉字的多字词 {} 个",
+                跳过多字词.len()
+            );
+        }
+        Ok((
             一字信息,
             多字信息,
             动态拆分,
@@ -536,7 +868,89 @@ impl 字源上下文 {
             数字转块,
             字根首笔,
             字根笔画,
-        )
+            字根末笔,
+            跳过多字词,
+        ))
+    }
+
+    /// 检查加载时不会报错、但会导致编码或优化失败的问题
+    pub fn 校验(&self) -> Vec<String> {
+        // 元素图中的环在新建时由拓扑排序报告，这里不再检查
+        let mut 问题列表 = self.检查决策(&self.初始决策, "初始决策");
+        for 词 in &self.跳过多字词 {
+            问题列表.push(format!("多字词信息：{词} 中有字不在汉字信息中"));
+        }
+        问题列表
+    }
+
+    /// 检查决策是否在决策空间中、满足所有条件，且每个块都有可行的拆分方式
+    pub fn 检查决策(&self, 决策: &字源决策, 来源: &str) -> Vec<String> {
+        let mut 问题列表 = vec![];
+        // 决策必须在决策空间中并满足条件，归并目标必须先于元素本身确定
+        for (元素, 安排列表) in self.决策空间.元素.iter().enumerate() {
+            if 安排列表.is_empty() {
+                continue;
+            }
+            let 名称 = &self.棱镜.数字转元素[&元素];
+            let 安排 = 决策.元素[元素];
+            if !安排列表.iter().any(|x| x.安排 == 安排 && 决策.允许(x)) {
+                问题列表.push(format!(
+                    "{来源}：元素 {名称:?} 的安排 {:?} 不在决策空间中或不满足条件",
+                    安排.to_mapped(&self.棱镜)
+                ));
+            }
+            if let 字源元素安排::归并(目标) = 安排 {
+                if 目标 >= 元素 {
+                    问题列表.push(format!(
+                        "{来源}：元素 {名称:?} 归并到的 {:?} 在拓扑序中位于其后",
+                        self.棱镜.数字转元素[&目标]
+                    ));
+                }
+            }
+        }
+        // 每个块都要有可行的拆分方式
+        let 映射 = 决策.线性化(&self.棱镜);
+        for (块序号, 拆分方式列表) in self.动态拆分.iter().enumerate() {
+            if !拆分方式列表
+                .iter()
+                .any(|拆分方式| 拆分方式.iter().all(|x| *x == 0 || 映射[*x] != 0))
+            {
+                问题列表.push(format!(
+                    "{来源}：块 {:?} 没有可行的拆分方式",
+                    self.数字转块[&块序号]
+                ));
+            }
+        }
+        问题列表
+    }
+
+    /// 从之前输出的配置文件中读取决策，用于继续优化
+    pub fn 读取决策(&self, 路径: &PathBuf) -> Result<字源决策, 错误> {
+        let 内容 = read_to_string(路径)
+            .map_err(|e| 错误::from(format!("无法读取决策文件 {路径:?}：{e}")))?;
+        let 结果配置: 配置 = from_str(&内容)
+            .map_err(|e| 错误::from(format!("决策文件 {路径:?} 格式错误：{e}")))?;
+        let mut 决策 = 字源决策 {
+            元素: vec![字源元素安排::未选取; self.初始决策.元素.len()],
+        };
+        let mut 问题列表 = vec![];
+        for (名称, mapped) in &结果配置.form.mapping {
+            let Some(&元素) = self.棱镜.元素转数字.get(名称) else {
+                问题列表.push(format!("{路径:?}：元素 {名称:?} 不在当前方案中"));
+                continue;
+            };
+            match 字源元素安排::from(mapped, &self.棱镜) {
+                Ok(安排) => 决策.元素[元素] = 安排,
+                Err(原因) => 问题列表.push(format!("{路径:?}：元素 {名称:?} {原因}")),
+            }
+        }
+        if 问题列表.is_empty() {
+            问题列表 = self.检查决策(&决策, &format!("{路径:?}"));
+        }
+        if !问题列表.is_empty() {
+            return Err(错误::from(问题列表.join("\n")));
+        }
+        Ok(决策)
     }
 
     // 分析前 3000 字中全码重码和简码差指法的情况
@@ -554,7 +968,7 @@ impl 字源上下文 {
             翻转码表
                 .entry(码表项.full.clone())
                 .or_insert_with(|| vec![])
-                .push((码表项.name.clone(), 编码结果[序号].频率));
+                .push((码表项.full_rank, 码表项.name.clone(), 编码结果[序号].频率));
         }
         let mut 差指法 = vec![];
         for 码表项 in 码表.iter().take(2000) {
@@ -583,12 +997,17 @@ impl 字源上下文 {
                 码表项.short_rank != 0
             };
             if 是重码 {
-                let mut 完整重码组 = 翻转码表[&码表项.full].clone();
-                let 位置 = 完整重码组
+                // 按候选位置而不是频率列出排在前面的候选
+                let mut 完整重码组: Vec<_> = 翻转码表[&码表项.full]
                     .iter()
-                    .position(|(x, _)| x == &码表项.name)
-                    .unwrap();
-                完整重码组.resize(位置, ("".to_string(), 0));
+                    .filter(|(位置, _, _)| *位置 < 码表项.full_rank)
+                    .cloned()
+                    .collect();
+                完整重码组.sort_by_key(|(位置, _, _)| *位置);
+                let 完整重码组: Vec<_> = 完整重码组
+                    .into_iter()
+                    .map(|(_, 词, 频率)| (词, 频率))
+                    .collect();
                 重码.push((码表项.name.clone(), 码表项.full.clone(), 完整重码组));
             }
         }
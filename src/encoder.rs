@@ -1,37 +1,103 @@
+use crate::config::{
+    字源候选排序, 字源取码规则, 字源取码项, 字源简码分配, 字源简码类型, 字源简码规则, 字源简码适用,
+    字源词规则,
+};
 use crate::context::{
-    一字信息项, 动态拆分项, 多字信息项, 字源上下文, 字源决策, 字源决策变化, 字源方案, 对齐, 方案,
-    最大码长, 特简字, 特简码, 空格, 线性化决策, 进制,
+    一字信息项, 动态拆分项, 多字信息项, 字源上下文, 字源决策, 字源决策变化, 最大码长上限, 码制,
+    线性化决策,
 };
+use crate::short_code::最优分配;
 use chai::{
     encoders::编码器, 元素, 棱镜, 码表项, 编码, 编码信息, 部分编码信息, 错误
 };
 use rustc_hash::FxHashMap;
 use std::iter::zip;
 
+/// 序号从 0 开始，负数表示从末尾倒数
+fn 按序号取(列表: &[usize], 序号: isize) -> Option<usize> {
+    let 位置 = if 序号 < 0 {
+        列表.len().checked_sub(序号.unsigned_abs())?
+    } else {
+        序号 as usize
+    };
+    列表.get(位置).copied()
+}
+
+/// 一个词最终得到的简码
+enum 简码结果 {
+    特简(编码),
+    /// 原始编码和实际编码
+    简码(编码, 编码),
+    /// 没有简码，以全码作为简码
+    全码,
+}
+
+/// 简码规则解析后的形式
+#[derive(Clone)]
+enum 简码级别 {
+    /// 词序号 -> 特简码
+    特简(FxHashMap<usize, 编码>),
+    前缀(字源简码规则),
+}
+
+#[derive(Clone)]
 pub struct 字源编码器 {
+    取码规则: Vec<字源取码规则>,
+    /// 多字词规则：适用的字数范围和 (字序号, 码序号) 列表
+    词规则: Vec<(字源词规则, Vec<(isize, isize)>)>,
+    pub 码制: 码制,
     pub 一字信息: Vec<一字信息项>,
     pub 一字索引: Vec<usize>,
     pub 多字信息: Vec<多字信息项>,
     pub 多字索引: Vec<usize>,
-    pub 多字转一字: Vec<[usize; 4]>,
+    pub 多字转一字: Vec<Vec<usize>>,
     pub 动态拆分: Vec<动态拆分项>,
-    pub 拆分序列: Vec<[元素; 4]>,
+    pub 拆分序列: Vec<[元素; 最大码长上限]>,
     pub _块转数字: FxHashMap<String, usize>,
     pub 数字转块: FxHashMap<usize, String>,
-    pub 全码编码空间: Vec<u8>,
-    pub 简码编码空间: Vec<u8>,
+    /// 简码编码 -> 已占用该编码的词数
+    pub 简码编码空间: Vec<u32>,
+    简码阶梯: Vec<简码级别>,
+    pub 简码分配: 字源简码分配,
+    /// 全码 -> 该全码下的词序号，按候选顺序排列
+    pub 全码桶: FxHashMap<编码, Vec<usize>>,
+    /// 词序号 -> 所属字词的标识，同一个字的不同读音标识相同
+    词标识: Vec<usize>,
+    /// 多音字的 (次要读音, 主读音) 词序号，主读音是频率最高的读音
+    多音读音: Vec<(usize, usize)>,
+    /// 与多音读音一一对应，上次分配简码时次要读音的全码是否与主读音相同
+    同码状态: Vec<bool>,
+    /// 词序号 -> 全码重码时的候选排名，越小越靠前
+    pub 候选次序: Vec<usize>,
+    /// 按候选排名排列的词序号
+    候选序列: Vec<usize>,
+    /// 元素 -> 拆分序列中含有该元素的词序号
+    pub 元素索引: Vec<Vec<usize>>,
+    /// 上一次编码时的线性化决策，用于找出键位发生变化的元素
+    pub 上一映射: 线性化决策,
+    /// 词序号 -> 该词在简码编码空间中占用的编码，特简码不占用
+    简码占位: Vec<Option<编码>>,
+    /// 阶梯中有笔画简码时，元素 -> 是否为某个字根的首笔
+    首笔元素: Vec<bool>,
+    待更新: Vec<bool>,
+    /// 每个词上一次编码后的简码，用于找出简码发生变化的词
+    简码快照: Vec<(编码, 编码, bool)>,
+    /// 本次编码中全码或简码可能发生变化的词序号，可能有重复
+    pub 变化词: Vec<usize>,
+    /// 本次编码是否进行了全量重建，此时变化词不完整
+    pub 全部变化: bool,
     pub 棱镜: 棱镜,
     pub 字根首笔: Vec<元素>,
     pub 字根笔画: Vec<(元素, 元素, 元素)>,
+    pub 字根末笔: Vec<u8>,
     pub 编码结果: Vec<编码信息>,
 }
 
 impl 字源编码器 {
     pub fn 新建(上下文: &字源上下文) -> Result<Self, 错误> {
-        let 编码空间大小 = 进制.pow(最大码长 as u32) as usize;
-        let 全码空间 = vec![u8::default(); 编码空间大小];
-        let 拆分序列 =
-            vec![Default::default(); 上下文.一字信息.len() + 上下文.多字信息.len()];
+        let 编码空间大小 = 上下文.码制.编码空间大小();
+        let 词数 = 上下文.一字信息.len() + 上下文.多字信息.len();
+        let 拆分序列 = vec![Default::default(); 词数];
         let mut 编码结果 = vec![];
         for (i, x) in 上下文.一字信息.iter().enumerate() {
             编码结果.push((
@@ -64,20 +130,94 @@ impl 字源编码器 {
         for (索引, (_, 原始索引, 是一字)) in 编码结果.iter().enumerate() {
             if *是一字 {
                 一字索引.push((索引, *原始索引));
-                汉字转索引.insert(上下文.一字信息[*原始索引].词, 索引);
+                // 多音字取频率最高的读音参与组词
+                汉字转索引
+                    .entry(上下文.一字信息[*原始索引].词)
+                    .or_insert(索引);
             } else {
                 多字索引.push((索引, *原始索引));
             }
         }
+        let 候选序列 = Self::候选排序(上下文, &编码结果);
+        let mut 候选次序 = vec![0; 词数];
+        for (排名, 序号) in 候选序列.iter().enumerate() {
+            候选次序[*序号] = 排名;
+        }
+        let 词标识 = 编码结果
+            .iter()
+            .enumerate()
+            .map(|(索引, (_, 原始索引, 是一字))| {
+                if *是一字 {
+                    汉字转索引[&上下文.一字信息[*原始索引].词]
+                } else {
+                    索引
+                }
+            })
+            .collect::<Vec<_>>();
+        let 多音读音: Vec<_> = 词标识
+            .iter()
+            .enumerate()
+            .filter(|(序号, 主读音)| *序号 != **主读音)
+            .map(|(序号, 主读音)| (序号, *主读音))
+            .collect();
         一字索引.sort_by(|a, b| a.1.cmp(&b.1));
         多字索引.sort_by(|a, b| a.1.cmp(&b.1));
+        let 简码阶梯 = 上下文
+            .字源配置
+            .encoder
+            .简码阶梯(上下文.码制.最大码长)
+            .into_iter()
+            .map(|规则| {
+                if 规则.类型 != 字源简码类型::特简 {
+                    return 简码级别::前缀(规则);
+                }
+                let mut 特简 = FxHashMap::default();
+                for 项 in &规则.特简 {
+                    // 多音字的特简码给频率最高的读音
+                    let Some(&序号) = 汉字转索引.get(&项.字) else {
+                        continue;
+                    };
+                    // 自定义的特简码已经校验过；默认特简码中有字母表之外的键时跳过
+                    let 编码 = 项.编码.chars().rev().try_fold(0, |编码, 键| {
+                        let 键 = 上下文.棱镜.键转数字.get(&键)?;
+                        Some(编码 * 上下文.码制.进制 + 键)
+                    });
+                    if let Some(编码) = 编码 {
+                        特简.insert(序号, 编码);
+                    }
+                }
+                简码级别::特简(特简)
+            })
+            .collect();
+        let mut 首笔元素 = vec![false; 上下文.初始决策.元素.len()];
+        let 有笔画简码 = 简码阶梯
+            .iter()
+            .any(|x| matches!(x, 简码级别::前缀(规则) if 规则.类型 == 字源简码类型::笔画));
+        if 有笔画简码 {
+            for 首笔 in &上下文.字根首笔 {
+                首笔元素[*首笔] = true;
+            }
+            首笔元素[0] = false;
+        }
         let mut 多字转一字 = vec![];
         for 多字信息项 in &上下文.多字信息 {
             let 多字: Vec<_> = 多字信息项.词.chars().map(|x| 汉字转索引[&x]).collect();
-            let 转换序列 = 对齐(多字, usize::MAX);
-            多字转一字.push(转换序列);
+            多字转一字.push(多字);
         }
         Ok(Self {
+            取码规则: 上下文.字源配置.encoder.取码规则列表(上下文.码制.最大码长),
+            词规则: 上下文
+                .字源配置
+                .encoder
+                .词规则列表(上下文.码制.最大码长)
+                .into_iter()
+                .map(|x| {
+                    // 上下文加载时已经校验过公式
+                    let 公式 = x.解析().unwrap();
+                    (x, 公式)
+                })
+                .collect(),
+            码制: 上下文.码制,
             动态拆分: 上下文.动态拆分.clone(),
             一字信息: 上下文.一字信息.clone(),
             一字索引: 一字索引.iter().map(|x| x.0).collect(),
@@ -87,37 +227,117 @@ impl 字源编码器 {
             拆分序列,
             _块转数字: 上下文.块转数字.clone(),
             数字转块: 上下文.数字转块.clone(),
-            全码编码空间: 全码空间.clone(),
-            简码编码空间: 全码空间.clone(),
+            简码编码空间: vec![0; 编码空间大小],
+            简码阶梯,
+            简码分配: 上下文.字源配置.encoder.简码分配,
+            全码桶: FxHashMap::default(),
+            词标识,
+            同码状态: vec![false; 多音读音.len()],
+            多音读音,
+            候选次序,
+            候选序列,
+            元素索引: vec![vec![]; 上下文.初始决策.元素.len()],
+            上一映射: vec![],
+            简码占位: vec![None; 词数],
+            首笔元素,
+            待更新: vec![false; 词数],
+            简码快照: vec![Default::default(); 词数],
+            变化词: vec![],
+            全部变化: true,
             棱镜: 上下文.棱镜.clone(),
             字根首笔: 上下文.字根首笔.clone(),
             字根笔画: 上下文.字根笔画.clone(),
+            字根末笔: 上下文.字根末笔.clone(),
             编码结果: 编码结果.iter().map(|x| x.0.clone()).collect(),
         })
     }
 
+    /// 编码结果已按频率降序排列，这里在此基础上按配置的策略稳定排序
+    fn 候选排序(
+        上下文: &字源上下文, 编码结果: &[(编码信息, usize, bool)]
+    ) -> Vec<usize> {
+        let mut 候选序列: Vec<_> = (0..编码结果.len()).collect();
+        match 上下文.字源配置.encoder.候选排序 {
+            字源候选排序::频率 => {}
+            字源候选排序::一字优先 => {
+                候选序列.sort_by_key(|x| !编码结果[*x].2);
+            }
+            字源候选排序::固定顺序 => {
+                let 固定位置: Vec<_> = 编码结果
+                    .iter()
+                    .map(|(_, 原始索引, 是一字)| {
+                        let 词 = if *是一字 {
+                            上下文.一字信息[*原始索引].词.to_string()
+                        } else {
+                            上下文.多字信息[*原始索引].词.clone()
+                        };
+                        上下文.固定顺序.get(&词).copied().unwrap_or(usize::MAX)
+                    })
+                    .collect();
+                候选序列.sort_by_key(|x| 固定位置[*x]);
+            }
+        }
+        候选序列
+    }
+
+    /// 按取码项取出一个元素，取不到时返回 None
+    fn 取码(
+        项: &字源取码项,
+        字根列表: &[元素],
+        一字信息项: &一字信息项,
+        字根首笔: &[元素],
+        字根笔画: &[(元素, 元素, 元素)],
+        字根末笔: &[u8],
+    ) -> Option<元素> {
+        let 拼音 = &一字信息项.拼音;
+        let (声母, 韵母) = 拼音.split_at(一字信息项.声母长度.min(拼音.len()));
+        match 项 {
+            字源取码项::字根 { 序号 } => 按序号取(字根列表, *序号),
+            字源取码项::首笔 { 序号 } => 按序号取(字根列表, *序号).map(|x| 字根首笔[x]),
+            字源取码项::笔画 { 序号, 笔画 } => {
+                let (第一笔, 第二笔, 末笔) = 字根笔画[按序号取(字根列表, *序号)?];
+                [第一笔, 第二笔, 末笔].get(*笔画).copied()
+            }
+            字源取码项::拼音 {
+                序号, 最短长度
+            } => {
+                if 拼音.len() < *最短长度 {
+                    return None;
+                }
+                按序号取(拼音, *序号)
+            }
+            字源取码项::声母 { 序号 } => 按序号取(声母, *序号),
+            字源取码项::韵母 { 序号 } => 按序号取(韵母, *序号),
+            字源取码项::识别码 { 序号 } => {
+                let 末笔 = 字根末笔[按序号取(字根列表, *序号)?] as usize;
+                let 识别码 = 一字信息项.识别码.as_ref()?;
+                识别码.get(末笔.checked_sub(1)?).copied()
+            }
+        }
+    }
+
     pub fn 构建元素序列(&mut self, 映射: &Vec<u64>, 决策: &字源决策) {
-        let mut 当前拆分索引 = vec![[0; 4]; self.动态拆分.len()];
+        let 最大码长 = self.码制.最大码长;
+        let mut 当前拆分索引 = vec![0; self.动态拆分.len()];
         for (指针, (_块序号, 拆分方式列表)) in
             zip(&mut 当前拆分索引, self.动态拆分.iter().enumerate())
         {
             let mut 找到 = false;
-            for 拆分方式 in 拆分方式列表.iter() {
+            for (拆分序号, 拆分方式) in 拆分方式列表.iter().enumerate() {
                 if 拆分方式.iter().all(|x| *x == 0 || 映射[*x] != 0) {
-                    *指针 = *拆分方式;
+                    *指针 = 拆分序号;
                     找到 = true;
                     break;
                 }
             }
             if !找到 {
                 let 块 = &self.数字转块[&_块序号];
-                let 拆分方式 = 拆分方式列表.last().unwrap().map(|x| {
-                    if x == 0 {
-                        "".to_string()
-                    } else {
-                        self.棱镜.数字转元素[&x].clone()
-                    }
-                });
+                let 拆分方式: Vec<_> = 拆分方式列表
+                    .last()
+                    .unwrap()
+                    .iter()
+                    .map(|x| self.棱镜.数字转元素[x].clone())
+                    .collect();
                 panic!(
                     "未找到 {块:?} 的映射: {拆分方式:?}\n当前决策为: {:?}",
                     决策.打印(&self.棱镜)
@@ -125,85 +345,127 @@ impl 字源编码器 {
             }
         }
         // 刷新单字元素序列
+        let mut 字根列表 = vec![];
         for (序号, 一字信息项) in zip(&self.一字索引, &self.一字信息) {
-            let 序列 = &mut self.拆分序列[*序号];
-            *序列 = [0; 4];
-            let mut index = 0;
-            for 块序号 in 一字信息项.字块 {
-                if 块序号 == usize::MAX {
-                    break;
-                }
-                for 元素 in 当前拆分索引[块序号] {
-                    if 元素 == 0 {
-                        break;
-                    }
-                    序列[index] = 元素;
-                    if index <= 2 {
-                        index += 1;
-                    }
-                }
+            字根列表.clear();
+            for 块序号 in &一字信息项.字块 {
+                字根列表.extend(&self.动态拆分[*块序号][当前拆分索引[*块序号]]);
             }
-            if 方案 == 字源方案::前缀 {
-                if 序列[1] == 0 {
-                    (序列[1], 序列[2], 序列[3]) = self.字根笔画[序列[0]];
-                } else if 序列[2] == 0 {
-                    序列[2] = self.字根首笔[序列[1]];
-                } else if 序列[3] == 0 {
-                    序列[3] = self.字根首笔[序列[2]];
+            let mut 序列 = [0; 最大码长上限];
+            let 规则 = self.取码规则.iter().find(|x| x.适用(字根列表.len()));
+            let mut 位置 = 0;
+            for 项 in 规则.map(|x| x.取码.as_slice()).unwrap_or(&[]) {
+                if 位置 >= 最大码长 {
+                    break;
                 }
-            } else {
-                let 全拼顺取 = 一字信息项.全拼顺取;
-                if 序列[1] == 0 {
-                    // 1个字根的字，根据拼音长度决定辅助码数量
-                    // 如果全拼只有一个字符，则辅助码只取一码
-                    if 全拼顺取[0] == 全拼顺取[1] && 全拼顺取[0] == 全拼顺取[2] {
-                        // 拼音只有1个字符，只取这1个字符作为辅助码
-                        (序列[1], 序列[2], 序列[3]) = (全拼顺取[0], 0, 0);
-                    } else {
-                        // 拼音有2个及以上字符，取第一个字符和最后一个字符作为辅助码
-                        (序列[1], 序列[2], 序列[3]) = (全拼顺取[0], 全拼顺取[2], 0);
-                    }
-                } else if 序列[2] == 0 {
-                    序列[2] = 全拼顺取[0];
-                } else if 序列[3] == 0 {
-                    序列[3] = 全拼顺取[0];
+                let 元素 = Self::取码(
+                    项,
+                    &字根列表,
+                    一字信息项,
+                    &self.字根首笔,
+                    &self.字根笔画,
+                    &self.字根末笔,
+                );
+                if let Some(元素) = 元素.filter(|x| *x != 0) {
+                    序列[位置] = 元素;
+                    位置 += 1;
                 }
             }
+            self.拆分序列[*序号] = 序列;
         }
         // 刷新多字元素序列
         for (序号, 多字转一字) in zip(&self.多字索引, &self.多字转一字) {
-            let mut 序列 = [0; 4];
-            let [字一, 字二, 字三, 字四] = *多字转一字;
-            if 字三 == usize::MAX {
-                // 二字词
-                序列[0] = self.拆分序列[字一][0];
-                序列[1] = self.拆分序列[字一][1];
-                序列[2] = self.拆分序列[字二][0];
-                序列[3] = self.拆分序列[字二][1];
-            } else if 字四 == usize::MAX {
-                // 三字词：只取每个字的第一码，共3码
-                序列[0] = self.拆分序列[字一][0];
-                序列[1] = self.拆分序列[字二][0];
-                序列[2] = self.拆分序列[字三][0];
-                序列[3] = 0;
-            } else {
-                // 四字词
-                序列[0] = self.拆分序列[字一][0];
-                序列[1] = self.拆分序列[字二][0];
-                序列[2] = self.拆分序列[字三][0];
-                序列[3] = self.拆分序列[字四][0];
+            let mut 序列 = [0; 最大码长上限];
+            let 规则 = self.词规则.iter().find(|x| x.0.适用(多字转一字.len()));
+            let mut 位置 = 0;
+            for (字序号, 码序号) in 规则.map(|x| x.1.as_slice()).unwrap_or(&[]) {
+                if 位置 >= 最大码长 {
+                    break;
+                }
+                let Some(字) = 按序号取(多字转一字, *字序号) else {
+                    continue;
+                };
+                let 字序列 = &self.拆分序列[字];
+                let 码数 = 字序列[..最大码长].iter().take_while(|x| **x != 0).count();
+                if let Some(元素) = 按序号取(&字序列[..码数], *码序号) {
+                    序列[位置] = 元素;
+                    位置 += 1;
+                }
             }
             self.拆分序列[*序号] = 序列;
         }
+        // 刷新元素索引
+        for 词序号列表 in &mut self.元素索引 {
+            词序号列表.clear();
+        }
+        for (序号, 序列) in self.拆分序列.iter().enumerate() {
+            for 元素 in 序列 {
+                let 词序号列表 = &mut self.元素索引[*元素];
+                if *元素 != 0 && 词序号列表.last() != Some(&序号) {
+                    词序号列表.push(序号);
+                }
+            }
+        }
+    }
+
+    /// 找出拆分序列中含有键位发生变化的元素的词
+    fn 受影响词(&mut self, 映射: &线性化决策) -> Vec<usize> {
+        let mut 受影响 = vec![];
+        for (元素, 词序号列表) in self.元素索引.iter().enumerate() {
+            if 映射[元素] == self.上一映射[元素] {
+                continue;
+            }
+            for 序号 in 词序号列表 {
+                if !self.待更新[*序号] {
+                    self.待更新[*序号] = true;
+                    受影响.push(*序号);
+                }
+            }
+        }
+        for 序号 in &受影响 {
+            self.待更新[*序号] = false;
+        }
+        受影响
+    }
+
+    /// 撤销候选排名在起点及之后的词对简码编码空间的占用，
+    /// 之前的词的占用保持不变，正是从起点开始重新分配时应有的状态
+    fn 重置简码空间(&mut self, 起点: usize) {
+        for 序号 in &self.候选序列[起点..] {
+            if let Some(编码) = self.简码占位[*序号].take() {
+                self.简码编码空间[编码 as usize] -= 1;
+            }
+        }
+    }
+
+    /// 简码需要从哪个候选排名开始重新分配。贪心分配中排名靠前的词不受排名靠后的词影响，
+    /// 所以只需从全码变化的词中排名最靠前的一个开始；笔画简码还依赖拆分和首笔元素的键位，
+    /// 这两者变化时从头开始。最优分配是全局的，总是从头开始
+    fn 简码起点(
+        &self, 全码起点: Option<usize>, 映射: &线性化决策, 拆分变化: bool
+    ) -> usize {
+        if self.简码分配 == 字源简码分配::最优 {
+            return 0;
+        }
+        let 有笔画简码 = self.首笔元素.iter().any(|x| *x);
+        let 首笔变化 = (0..映射.len()).any(|x| self.首笔元素[x] && 映射[x] != self.上一映射[x]);
+        if 有笔画简码 && (拆分变化 || 首笔变化) {
+            return 0;
+        }
+        全码起点.unwrap_or(self.候选序列.len())
     }
 
-    pub fn 重置空间(&mut self) {
-        self.全码编码空间.iter_mut().for_each(|x| {
-            *x = 0;
-        });
-        self.简码编码空间.iter_mut().for_each(|x| {
-            *x = 0;
-        });
+    /// 次要读音的全码与主读音相同时不占用简码空间，这一点变化时要从该读音的排名开始重新分配
+    fn 次要读音起点(&mut self, mut 起点: usize) -> usize {
+        for 序号 in 0..self.多音读音.len() {
+            let (次要读音, _) = self.多音读音[序号];
+            let 同码 = self.同码次要读音(次要读音);
+            if 同码 != self.同码状态[序号] {
+                self.同码状态[序号] = 同码;
+                起点 = 起点.min(self.候选次序[次要读音]);
+            }
+        }
+        起点
     }
 
     pub fn 生成码表(&self) -> Vec<码表项> {
@@ -236,105 +498,347 @@ impl 字源编码器 {
     }
 
     #[inline(always)]
-    fn 全码规则(元素序列: &[元素; 4], 映射: &线性化决策) -> u64 {
-        映射[元素序列[0]]
-            + (映射[元素序列[1]]) * 进制
-            + (映射[元素序列[2]]) * 进制 * 进制
-            + (映射[元素序列[3]]) * 进制 * 进制 * 进制
+    fn 输出全码(&mut self, 映射: &线性化决策) {
+        self.全码桶.clear();
+        for (序列, 编码信息) in zip(self.拆分序列.iter(), self.编码结果.iter_mut()) {
+            let 全码信息 = &mut 编码信息.全码;
+            全码信息.原始编码 = self.码制.全码(序列, 映射);
+            全码信息.实际编码 = self.码制.补空格(全码信息.原始编码);
+        }
+        // 按候选排名依次放入桶中，桶内的词序号也就按候选顺序排列
+        for 序号 in &self.候选序列 {
+            let 编码 = self.编码结果[*序号].全码.原始编码;
+            self.全码桶.entry(编码).or_default().push(*序号);
+        }
+        let 编码列表: Vec<_> = self.全码桶.keys().copied().collect();
+        for 编码 in 编码列表 {
+            self.排列候选(编码);
+        }
+    }
+
+    /// 多音字除频率最高的读音外都不出简码，直接用全码
+    fn 次要读音(&self, 序号: usize) -> bool {
+        self.词标识[序号] != 序号
+    }
+
+    /// 全码与主读音相同的次要读音和主读音是同一个码表条目，简码也照搬主读音
+    fn 同码次要读音(&self, 序号: usize) -> bool {
+        let 主读音 = self.词标识[序号];
+        主读音 != 序号 && self.编码结果[序号].全码.原始编码 == self.编码结果[主读音].全码.原始编码
+    }
+
+    /// 在分配完其他词的简码之后，让全码与主读音相同的次要读音照搬主读音的简码
+    fn 同步次要读音(&mut self) {
+        for (次要读音, 主读音) in self.多音读音.clone() {
+            if self.同码次要读音(次要读音) {
+                self.编码结果[次要读音].简码 = self.编码结果[主读音].简码.clone();
+            }
+        }
+    }
+
+    /// 按桶内顺序分配候选位置，同一个字的不同读音编码相同时只占一个位置
+    fn 排列候选(&mut self, 编码: 编码) {
+        let 桶 = self.全码桶.get(&编码).map(|x| x.as_slice()).unwrap_or(&[]);
+        let mut 候选数 = 0;
+        for (桶内序号, 序号) in 桶.iter().enumerate() {
+            let 同字读音 = 桶[..桶内序号]
+                .iter()
+                .find(|x| self.词标识[**x] == self.词标识[*序号]);
+            let 位置 = match 同字读音 {
+                Some(同字序号) => self.编码结果[*同字序号].全码.原始编码候选位置,
+                None => {
+                    候选数 += 1;
+                    (候选数 - 1).min(u8::MAX as usize) as u8
+                }
+            };
+            let 全码信息 = &mut self.编码结果[*序号].全码;
+            全码信息.原始编码候选位置 = 位置;
+            全码信息.选重标记 = 位置 > 0;
+        }
+    }
+
+    /// 只重新计算候选词的全码，并更新这些词新旧全码下所有词的候选位置。
+    /// 返回全码发生变化的词中最靠前的候选排名
+    fn 增量输出全码(&mut self, 候选: &[usize], 映射: &线性化决策) -> Option<usize> {
+        let mut 变化编码 = vec![];
+        let mut 最前排名: Option<usize> = None;
+        let 候选次序 = &self.候选次序;
+        for 序号 in 候选 {
+            let 新编码 = self.码制.全码(&self.拆分序列[*序号], 映射);
+            let 全码信息 = &mut self.编码结果[*序号].全码;
+            let 旧编码 = 全码信息.原始编码;
+            if 新编码 == 旧编码 {
+                continue;
+            }
+            全码信息.原始编码 = 新编码;
+            全码信息.实际编码 = self.码制.补空格(新编码);
+            let 排名 = 候选次序[*序号];
+            最前排名 = Some(最前排名.map_or(排名, |x| x.min(排名)));
+            if let Some(旧桶) = self.全码桶.get_mut(&旧编码) {
+                if let Ok(位置) =
+                    旧桶.binary_search_by_key(&候选次序[*序号], |x| 候选次序[*x])
+                {
+                    旧桶.remove(位置);
+                }
+                if 旧桶.is_empty() {
+                    self.全码桶.remove(&旧编码);
+                }
+            }
+            let 新桶 = self.全码桶.entry(新编码).or_default();
+            if let Err(位置) = 新桶.binary_search_by_key(&候选次序[*序号], |x| 候选次序[*x])
+            {
+                新桶.insert(位置, *序号);
+            }
+            变化编码.push(旧编码);
+            变化编码.push(新编码);
+        }
+        for 编码 in 变化编码 {
+            if let Some(桶) = self.全码桶.get(&编码) {
+                self.变化词.extend_from_slice(桶);
+            }
+            self.排列候选(编码);
+        }
+        最前排名
     }
 
-    fn 补空格(编码: u64) -> u64 {
-        if 编码 < 进制 * 进制 {
-            编码 + 空格 * 进制 * 进制
-        } else if 编码 < 进制 * 进制 * 进制 {
-            编码 + 空格 * 进制 * 进制 * 进制
+    /// 起点之前的次要读音可能随起点之后的主读音变化，也要检查
+    fn 记录简码变化(&mut self, 起点: usize) {
+        let 次要读音 = self
+            .多音读音
+            .iter()
+            .map(|x| x.0)
+            .filter(|x| self.候选次序[*x] < 起点);
+        let 检查列表: Vec<_> = self.候选序列[起点..]
+            .iter()
+            .copied()
+            .chain(次要读音)
+            .collect();
+        for 序号 in 检查列表 {
+            let 简码 = &self.编码结果[序号].简码;
+            let 当前 = (简码.原始编码, 简码.实际编码, 简码.选重标记);
+            if self.简码快照[序号] != 当前 {
+                self.简码快照[序号] = 当前;
+                self.变化词.push(序号);
+            }
+        }
+    }
+
+    /// 按一级简码规则求出原始编码和实际编码，不适用时返回 None
+    fn 级别简码(
+        规则: &字源简码规则,
+        编码信息: &编码信息,
+        拆分: &[元素; 最大码长上限],
+        字根首笔: &[元素],
+        映射: &线性化决策,
+        码制: &码制,
+    ) -> Option<(编码, 编码)> {
+        let 进制 = 码制.进制;
+        let 全码 = 编码信息.全码.原始编码;
+        let 适用 = match 规则.适用 {
+            字源简码适用::一字 => 编码信息.词长 == 1,
+            字源简码适用::多字 => 编码信息.词长 > 1,
+            字源简码适用::全部 => true,
+        };
+        if !适用 || 码制.码长(全码) < 规则.最短全码 {
+            return None;
+        }
+        let 长度 = 规则.长度 as u32;
+        let mut 简码 = 全码 % 进制.pow(长度);
+        if 规则.类型 == 字源简码类型::笔画 {
+            // 最后一码换成对应字根的首笔，字根不足时用最后一个字根
+            let 位置 = (规则.长度 - 1).min(拆分.iter().rposition(|x| *x != 0).unwrap_or(0));
+            let 笔画 = 字根首笔[拆分[位置]];
+            简码 = 简码 % 进制.pow(长度 - 1) + 映射[笔画] * 进制.pow(长度 - 1);
+        }
+        let 带空格 = 简码 + 码制.空格 * 进制.pow(长度);
+        let 原始编码 = if 规则.空格计入编码 {
+            带空格
         } else {
-            编码
+            简码
+        };
+        let 实际编码 = if 规则.补空格 || 规则.空格计入编码 {
+            带空格
+        } else {
+            简码
+        };
+        Some((原始编码, 实际编码))
+    }
+
+    /// 最优分配是全局的，每次都要对全部词重新求解；贪心分配只重算起点之后的词
+    fn 输出简码(&mut self, 映射: &线性化决策, 起点: usize) {
+        self.重置简码空间(起点);
+        match self.简码分配 {
+            字源简码分配::贪心 => self.贪心输出简码(映射, 起点),
+            字源简码分配::最优 => self.最优输出简码(映射),
         }
+        self.同步次要读音();
     }
 
-    fn 输出全码(&mut self, _决策: &字源决策, 映射: &线性化决策) {
-        for (序列, 编码信息) in zip(self.拆分序列.iter(), self.编码结果.iter_mut()) {
-            let 全码信息 = &mut 编码信息.全码;
-            全码信息.原始编码 = Self::全码规则(序列, &映射);
-            全码信息.实际编码 = Self::补空格(全码信息.原始编码);
-            全码信息.原始编码候选位置 = self.全码编码空间[全码信息.原始编码 as usize];
-            self.全码编码空间[全码信息.原始编码 as usize] += 1;
-            全码信息.选重标记 = 全码信息.原始编码候选位置 > 0;
+    /// 占用简码编码空间中的一个位置，返回候选位置
+    fn 占用简码(&mut self, 序号: usize, 编码: 编码) -> u8 {
+        let 位置 = self.简码编码空间[编码 as usize];
+        self.简码编码空间[编码 as usize] += 1;
+        self.简码占位[序号] = Some(编码);
+        位置.min(u8::MAX as u32) as u8
+    }
+
+    /// 按候选排名依次让每个词取阶梯中第一个还有空位的简码，
+    /// 同一简码下的候选也就按候选排序策略排列
+    fn 贪心输出简码(&mut self, 映射: &线性化决策, 起点: usize) {
+        for 排名 in 起点..self.候选序列.len() {
+            let 序号 = self.候选序列[排名];
+            if self.同码次要读音(序号) {
+                continue;
+            }
+            let 结果 = self.贪心选取简码(序号, 映射);
+            self.写入简码(序号, 结果);
         }
     }
 
-    fn 输出简码(&mut self, 映射: &线性化决策) {
-        for (序号, 编码信息) in self.编码结果.iter_mut().enumerate() {
-            编码信息.简码.原始编码候选位置 = 0;
-            编码信息.简码.选重标记 = false;
-            let 全码 = 编码信息.全码.原始编码;
-            let 拆分 = self.拆分序列[序号];
-            if 编码信息.词长 == 1 {
-                if 方案 == 字源方案::前缀 {
-                    // 特简码
-                    let mut 有特简码 = false;
-                    for (编码, 字) in zip(特简码.iter(), 特简字.iter()) {
-                        if self.一字信息[序号].词 == *字 {
-                            let 特简 = self.棱镜.键转数字[&编码];
-                            编码信息.简码.原始编码 = 特简;
-                            有特简码 = true;
+    fn 贪心选取简码(&self, 序号: usize, 映射: &线性化决策) -> 简码结果 {
+        if self.次要读音(序号) {
+            return 简码结果::全码;
+        }
+        let 编码信息 = &self.编码结果[序号];
+        for 级别 in &self.简码阶梯 {
+            let 规则 = match 级别 {
+                简码级别::特简(特简) => {
+                    if let Some(特简码) = 特简.get(&序号) {
+                        return 简码结果::特简(*特简码);
+                    }
+                    continue;
+                }
+                简码级别::前缀(规则) => 规则,
+            };
+            let 拆分 = &self.拆分序列[序号];
+            let Some((原始编码, 实际编码)) =
+                Self::级别简码(规则, 编码信息, 拆分, &self.字根首笔, 映射, &self.码制)
+            else {
+                continue;
+            };
+            if self.简码编码空间[原始编码 as usize] >= 规则.数量 as u32 {
+                continue;
+            }
+            return 简码结果::简码(原始编码, 实际编码);
+        }
+        简码结果::全码
+    }
+
+    /// 写入一个词的简码；除特简码外都占用简码编码空间，没有简码的词以全码占用
+    fn 写入简码(&mut self, 序号: usize, 结果: 简码结果) {
+        let (原始编码, 实际编码, 占用) = match 结果 {
+            简码结果::特简(编码) => (编码, 编码, false),
+            简码结果::简码(原始编码, 实际编码) => (原始编码, 实际编码, true),
+            简码结果::全码 => {
+                let 全码 = &self.编码结果[序号].全码;
+                (全码.原始编码, 全码.实际编码, true)
+            }
+        };
+        let 位置 = if 占用 {
+            self.占用简码(序号, 原始编码)
+        } else {
+            0
+        };
+        let 简码 = &mut self.编码结果[序号].简码;
+        简码.原始编码 = 原始编码;
+        简码.实际编码 = 实际编码;
+        简码.原始编码候选位置 = 位置;
+        简码.选重标记 = 位置 > 0;
+    }
+
+    /// 把每个简码看作容量有限的槽位，求使总键数最少的分配。
+    /// 特简码是固定的，不参与分配；没有分到简码的词用全码。
+    ///
+    /// 费用只计频率乘以码长之差，不含当量：当量依赖目标函数的权重和指法数据，
+    /// 编码器中拿不到，简码对当量的影响仍由目标函数在评估时计入
+    fn 最优输出简码(&mut self, 映射: &线性化决策) {
+        let mut 槽位序号 = FxHashMap::default();
+        let mut 容量 = vec![];
+        let mut 槽位编码 = vec![];
+        let mut 候选 = vec![];
+        let mut 特简码 = vec![None; self.编码结果.len()];
+        for (序号, 编码信息) in self.编码结果.iter().enumerate() {
+            let mut 词候选 = vec![];
+            if self.次要读音(序号) {
+                候选.push(词候选);
+                continue;
+            }
+            let 全码长度 = self.码制.码长(编码信息.全码.实际编码) as i64;
+            for 级别 in &self.简码阶梯 {
+                let 规则 = match 级别 {
+                    简码级别::特简(特简) => {
+                        if let Some(编码) = 特简.get(&序号) {
+                            特简码[序号] = Some(*编码);
+                            词候选.clear();
                             break;
                         }
-                    }
-                    if 有特简码 {
                         continue;
                     }
-                }
-                // 一级简码（空格）
-                let 一简 = 全码 % 进制;
-                if self.简码编码空间[一简 as usize] == 0 {
-                    编码信息.简码.原始编码 = 一简;
-                    编码信息.简码.实际编码 = 一简 + 空格 * 进制;
-                    self.简码编码空间[一简 as usize] += 1;
+                    简码级别::前缀(规则) => 规则,
+                };
+                let 拆分 = &self.拆分序列[序号];
+                let Some((原始编码, 实际编码)) =
+                    Self::级别简码(规则, 编码信息, 拆分, &self.字根首笔, 映射, &self.码制)
+                else {
+                    continue;
+                };
+                let 费用 = 编码信息.频率 as i64 * (self.码制.码长(实际编码) as i64 - 全码长度);
+                if 费用 >= 0 {
                     continue;
                 }
-                if 方案 == 字源方案::前缀 {
-                    // 一级简码（笔画）
-                    let 笔画 = if 拆分[1] == 0 {
-                        self.字根首笔[拆分[0]]
-                    } else {
-                        self.字根首笔[拆分[1]]
-                    };
-                    let 准一简 = 全码 % 进制 + 映射[笔画] * 进制;
-                    if self.简码编码空间[准一简 as usize] == 0 {
-                        编码信息.简码.原始编码 = 准一简;
-                        self.简码编码空间[准一简 as usize] += 1;
-                        continue;
-                    }
-                    // 二级简码
-                    if 全码 > 进制 * 进制 * 进制 {
-                        let 二简 = 全码 % (进制 * 进制) + 空格 * 进制 * 进制;
-                        if self.简码编码空间[二简 as usize] == 0 {
-                            编码信息.简码.原始编码 = 二简;
-                            self.简码编码空间[二简 as usize] += 1;
-                            continue;
-                        }
-                    }
-                } else {
-                    // 二级简码
-                    if 全码 > 进制 * 进制 {
-                        let 二简 = 全码 % (进制 * 进制);
-                        if self.简码编码空间[二简 as usize] == 0 {
-                            编码信息.简码.原始编码 = 二简;
-                            编码信息.简码.实际编码 = 二简 + 空格 * 进制 * 进制;
-                            self.简码编码空间[二简 as usize] += 1;
-                            continue;
-                        }
-                    }
+                let 槽位 = *槽位序号.entry(原始编码).or_insert_with(|| {
+                    容量.push(规则.数量);
+                    槽位编码.push((原始编码, 实际编码));
+                    容量.len() - 1
+                });
+                容量[槽位] = 容量[槽位].min(规则.数量);
+                词候选.push((槽位, 费用));
+            }
+            候选.push(词候选);
+        }
+        // 没有分到简码的词以全码占用简码空间，全码恰好是某个槽位的编码时会挤占该槽位。
+        // 先扣除一定用全码的词，求解后再扣除新出现的用全码的词，直到不再变化
+        let 全码槽位 = |序号: usize| {
+            if 特简码[序号].is_some() || self.同码次要读音(序号) {
+                return None;
+            }
+            槽位序号.get(&self.编码结果[序号].全码.原始编码).copied()
+        };
+        let mut 占位: Vec<_> = (0..候选.len())
+            .map(|x| 候选[x].is_empty() && 全码槽位(x).is_some())
+            .collect();
+        let 分配结果 = loop {
+            let mut 剩余容量 = 容量.clone();
+            for 序号 in (0..候选.len()).filter(|x| 占位[*x]) {
+                if let Some(槽位) = 全码槽位(序号) {
+                    剩余容量[槽位] = 剩余容量[槽位].saturating_sub(1);
                 }
             }
-            // 无简码
-            编码信息.简码.原始编码 = 全码;
-            编码信息.简码.实际编码 = 编码信息.全码.实际编码;
-            编码信息.简码.原始编码候选位置 = self.简码编码空间[全码 as usize];
-            self.简码编码空间[全码 as usize] += 1;
-            编码信息.简码.选重标记 = 编码信息.简码.原始编码候选位置 > 0;
+            let 分配结果 = 最优分配(&候选, &剩余容量);
+            let mut 新增 = false;
+            for 序号 in 0..候选.len() {
+                if !占位[序号] && 分配结果[序号].is_none() && 全码槽位(序号).is_some()
+                {
+                    占位[序号] = true;
+                    新增 = true;
+                }
+            }
+            if !新增 {
+                break 分配结果;
+            }
+        };
+        // 按候选排名写入，同一槽位中的词和没有简码的词都按候选排序策略排列
+        for 排名 in 0..self.候选序列.len() {
+            let 序号 = self.候选序列[排名];
+            if self.同码次要读音(序号) {
+                continue;
+            }
+            let 结果 = match (特简码[序号], 分配结果[序号]) {
+                (Some(编码), _) => 简码结果::特简(编码),
+                (None, Some(槽位)) => 简码结果::简码(槽位编码[槽位].0, 槽位编码[槽位].1),
+                (None, None) => 简码结果::全码,
+            };
+            self.写入简码(序号, 结果);
         }
     }
 }
@@ -345,15 +849,32 @@ impl 编码器 for 字源编码器 {
         &mut self, 决策: &字源决策, 决策变化: &Option<字源决策变化>, _输出: &mut [编码信息]
     ) {
         let 映射 = 决策.线性化(&self.棱镜);
-        if let Some(变化) = 决策变化 {
-            if 变化.增加字根.len() > 0 || 变化.减少字根.len() > 0 {
+        self.变化词.clear();
+        self.全部变化 = false;
+        let 简码起点 = match 决策变化 {
+            Some(变化) if self.上一映射.len() == 映射.len() => {
+                let 拆分变化 = 变化.增加字根.len() > 0 || 变化.减少字根.len() > 0;
+                let 全码起点 = if 拆分变化 {
+                    // 拆分可能改变，重建元素序列后逐个比较全码
+                    self.构建元素序列(&映射, 决策);
+                    let 全部: Vec<_> = (0..self.编码结果.len()).collect();
+                    self.增量输出全码(&全部, &映射)
+                } else {
+                    let 受影响 = self.受影响词(&映射);
+                    self.增量输出全码(&受影响, &映射)
+                };
+                self.简码起点(全码起点, &映射, 拆分变化)
+            }
+            _ => {
                 self.构建元素序列(&映射, 决策);
+                self.输出全码(&映射);
+                self.全部变化 = true;
+                0
             }
-        } else {
-            self.构建元素序列(&映射, 决策);
         };
-        self.重置空间();
-        self.输出全码(决策, &映射);
-        self.输出简码(&映射);
+        let 简码起点 = self.次要读音起点(简码起点);
+        self.输出简码(&映射, 简码起点);
+        self.记录简码变化(简码起点);
+        self.上一映射 = 映射;
     }
 }
@@ -0,0 +1,241 @@
+use crate::command_line::导出格式;
+use crate::context::字源上下文;
+use chai::{码表项, 编码信息, 错误};
+use chrono::Local;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{fs::File, io::Write, path::Path};
+
+/// 码表中的一个条目，简码和全码各为一条
+pub struct 导出项 {
+    pub 词: String,
+    /// 去掉末尾补码空格的编码，码长不足时由空格选择首选
+    pub 编码: String,
+    /// 同一编码下的候选位置
+    pub 次序: u8,
+    pub 频率: u64,
+}
+
+/// 把码表展开为全码和简码条目，按编码和候选位置排序
+///
+/// 简码与全码相同的只保留一条，多音字编码相同的读音也只保留一条
+pub fn 收集导出项(码表: &[码表项], 编码结果: &[编码信息]) -> Vec<导出项> {
+    let mut 导出列表 = vec![];
+    let mut 已导出 = FxHashSet::default();
+    for (码表项, 编码信息) in 码表.iter().zip(编码结果) {
+        // 空格只出现在码长不足时的末尾，在各输入法中都对应空格选择首选
+        let 全码 = 码表项.full.trim_end_matches('_').to_string();
+        let 简码 = 码表项.short.trim_end_matches('_').to_string();
+        for (编码, 次序) in [(简码, 码表项.short_rank), (全码, 码表项.full_rank)] {
+            if 编码.is_empty() || !已导出.insert((码表项.name.clone(), 编码.clone())) {
+                continue;
+            }
+            导出列表.push(导出项 {
+                词: 码表项.name.clone(),
+                编码,
+                次序,
+                频率: 编码信息.频率,
+            });
+        }
+    }
+    // 稳定排序，次序相同时保持频率顺序
+    导出列表.sort_by(|a, b| (&a.编码, a.次序).cmp(&(&b.编码, b.次序)));
+    导出列表
+}
+
+/// 由频率得到权重，并保证同一编码下候选位置靠前的权重更高
+fn 计算权重(导出列表: &[导出项]) -> Vec<u64> {
+    let mut 权重 = vec![0; 导出列表.len()];
+    let mut 下一项: FxHashMap<&str, u64> = FxHashMap::default();
+    for (序号, 项) in 导出列表.iter().enumerate().rev() {
+        let 当前 = match 下一项.get(项.编码.as_str()) {
+            Some(后一项权重) => 项.频率.max(后一项权重 + 1),
+            None => 项.频率,
+        };
+        下一项.insert(项.编码.as_str(), 当前);
+        权重[序号] = 当前;
+    }
+    权重
+}
+
+pub fn 输出码表(
+    格式: 导出格式,
+    上下文: &字源上下文,
+    导出列表: &[导出项],
+    名称: &str,
+    目录: &Path,
+) -> Result<(), 错误> {
+    std::fs::create_dir_all(目录)?;
+    match 格式 {
+        导出格式::Rime => 输出中州韵码表(上下文, 导出列表, 名称, 目录),
+        导出格式::Fcitx5 => 输出小企鹅码表(上下文, 导出列表, 名称, 目录),
+        导出格式::Duoduo => 输出多多码表(导出列表, 名称, 目录),
+        导出格式::Xiaoxiao => 输出小小码表(上下文, 导出列表, 名称, 目录),
+    }
+}
+
+/// 除去补码空格的按键，按字母表中的顺序
+fn 按键列表(上下文: &字源上下文) -> String {
+    上下文
+        .配置
+        .form
+        .alphabet
+        .chars()
+        .filter(|x| *x != '_')
+        .collect()
+}
+
+/// 把按键写成正则表达式字符类的内容，用在 YAML 单引号字符串中
+fn 字符类(按键: &str) -> String {
+    let mut 结果 = String::new();
+    for 键 in 按键.chars() {
+        match 键 {
+            '\\' | ']' | '[' | '^' | '-' => {
+                结果.push('\\');
+                结果.push(键);
+            }
+            '\'' => 结果.push_str("''"),
+            _ => 结果.push(键),
+        }
+    }
+    结果
+}
+
+/// 输出 Rime 词典 `{名称}.dict.yaml` 和对应的方案 `{名称}.schema.yaml`
+fn 输出中州韵码表(
+    上下文: &字源上下文,
+    导出列表: &[导出项],
+    名称: &str,
+    目录: &Path,
+) -> Result<(), 错误> {
+    let 版本 = Local::now().format("%Y-%m-%d").to_string();
+    let 权重 = 计算权重(导出列表);
+    let mut 词典 = File::create(目录.join(format!("{名称}.dict.yaml")))?;
+    writeln!(词典, "# Rime dictionary")?;
+    writeln!(词典, "# encoding: utf-8")?;
+    writeln!(词典, "---")?;
+    writeln!(词典, "name: {名称}")?;
+    writeln!(词典, "version: \"{版本}\"")?;
+    writeln!(词典, "sort: by_weight")?;
+    writeln!(词典, "use_preset_vocabulary: false")?;
+    writeln!(词典, "...")?;
+    writeln!(词典)?;
+    for (项, 权重) in 导出列表.iter().zip(权重) {
+        writeln!(词典, "{}\t{}\t{权重}", 项.词, 项.编码)?;
+    }
+    // 码长不足时补空格：不满码长的编码不自动上屏，由空格选择首选
+    let 字母表 = 按键列表(上下文);
+    let 最大码长 = 上下文.码制.最大码长;
+    let mut 方案 = File::create(目录.join(format!("{名称}.schema.yaml")))?;
+    writeln!(方案, "# Rime schema")?;
+    writeln!(方案, "# encoding: utf-8")?;
+    writeln!(方案)?;
+    writeln!(方案, "schema:")?;
+    writeln!(方案, "  schema_id: {名称}")?;
+    writeln!(方案, "  name: {名称}")?;
+    writeln!(方案, "  version: \"{版本}\"")?;
+    writeln!(方案)?;
+    writeln!(方案, "engine:")?;
+    writeln!(方案, "  processors:")?;
+    for 处理器 in [
+        "ascii_composer",
+        "recognizer",
+        "key_binder",
+        "speller",
+        "punctuator",
+        "selector",
+        "navigator",
+        "express_editor",
+    ] {
+        writeln!(方案, "    - {处理器}")?;
+    }
+    writeln!(方案, "  segmentors:")?;
+    for 分段器 in [
+        "ascii_segmentor",
+        "matcher",
+        "abc_segmentor",
+        "punct_segmentor",
+        "fallback_segmentor",
+    ] {
+        writeln!(方案, "    - {分段器}")?;
+    }
+    writeln!(方案, "  translators:")?;
+    writeln!(方案, "    - punct_translator")?;
+    writeln!(方案, "    - table_translator")?;
+    writeln!(方案)?;
+    writeln!(方案, "speller:")?;
+    writeln!(
+        方案,
+        "  alphabet: \"{}\"",
+        字母表.replace('\\', "\\\\").replace('"', "\\\"")
+    )?;
+    writeln!(方案, "  max_code_length: {最大码长}")?;
+    writeln!(方案, "  auto_select: true")?;
+    writeln!(
+        方案,
+        "  auto_select_pattern: '^[{}]{{{最大码长}}}$'",
+        字符类(&字母表)
+    )?;
+    writeln!(方案, "  auto_clear: max_length")?;
+    writeln!(方案)?;
+    writeln!(方案, "translator:")?;
+    writeln!(方案, "  dictionary: {名称}")?;
+    writeln!(方案, "  enable_charset_filter: false")?;
+    writeln!(方案, "  enable_sentence: false")?;
+    writeln!(方案, "  enable_completion: false")?;
+    writeln!(方案, "  enable_user_dict: false")?;
+    writeln!(方案, "  enable_encoder: false")?;
+    writeln!(方案)?;
+    writeln!(方案, "punctuator:")?;
+    writeln!(方案, "  import_preset: default")?;
+    writeln!(方案)?;
+    writeln!(方案, "recognizer:")?;
+    writeln!(方案, "  import_preset: default")?;
+    Ok(())
+}
+
+/// 输出 Fcitx5 码表文本 `{名称}.fcitx5.txt`，同一编码下按文件中的顺序排列候选
+fn 输出小企鹅码表(
+    上下文: &字源上下文,
+    导出列表: &[导出项],
+    名称: &str,
+    目录: &Path,
+) -> Result<(), 错误> {
+    let mut 文件 = File::create(目录.join(format!("{名称}.fcitx5.txt")))?;
+    writeln!(文件, "KeyCode={}", 按键列表(上下文))?;
+    writeln!(文件, "Length={}", 上下文.码制.最大码长)?;
+    writeln!(文件, "[Data]")?;
+    for 项 in 导出列表 {
+        writeln!(文件, "{} {}", 项.编码, 项.词)?;
+    }
+    Ok(())
+}
+
+/// 输出多多输入法码表 `{名称}.duoduo.txt`，每行为字词和编码
+fn 输出多多码表(
+    导出列表: &[导出项], 名称: &str, 目录: &Path
+) -> Result<(), 错误> {
+    let mut 文件 = File::create(目录.join(format!("{名称}.duoduo.txt")))?;
+    for 项 in 导出列表 {
+        writeln!(文件, "{}\t{}", 项.词, 项.编码)?;
+    }
+    Ok(())
+}
+
+/// 输出小小输入法码表 `{名称}.xiaoxiao.txt`，同一编码的候选写在同一行
+fn 输出小小码表(
+    上下文: &字源上下文,
+    导出列表: &[导出项],
+    名称: &str,
+    目录: &Path,
+) -> Result<(), 错误> {
+    let mut 文件 = File::create(目录.join(format!("{名称}.xiaoxiao.txt")))?;
+    writeln!(文件, "name={名称}")?;
+    writeln!(文件, "key={}", 按键列表(上下文))?;
+    writeln!(文件, "len={}", 上下文.码制.最大码长)?;
+    writeln!(文件, "[DATA]")?;
+    for 同码项 in 导出列表.chunk_by(|a, b| a.编码 == b.编码) {
+        let 词列表: Vec<_> = 同码项.iter().map(|x| x.词.as_str()).collect();
+        writeln!(文件, "{} {}", 同码项[0].编码, 词列表.join(" "))?;
+    }
+    Ok(())
+}
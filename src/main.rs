@@ -1,11 +1,13 @@
+use crate::chart::输出字根图;
+use crate::command_line::{字源命令, 字源命令行参数};
+use crate::config::{字源简码分配, 字源配置};
 use crate::context::字源上下文;
 use crate::encoder::字源编码器;
+use crate::export::{收集导出项, 输出码表};
 use crate::objective::字源目标函数;
 use crate::operator::字源操作;
-use chai::config::SolverConfig;
-use chai::interfaces::command_line::{
-    从命令行参数创建, 命令, 命令行, 默认命令行参数
-};
+use crate::solver::字源求解器;
+use chai::interfaces::command_line::{从命令行参数创建, 命令行};
 use chai::objectives::目标函数;
 use chai::错误;
 use clap::Parser;
@@ -13,47 +15,105 @@ use std::fs::File;
 use std::io::Write;
 use std::thread::spawn;
 
+mod chart;
+mod command_line;
+mod config;
 mod context;
 mod encoder;
+mod export;
 mod objective;
 mod operator;
+mod short_code;
+mod solver;
+
+fn 输出校验报告(加载结果: Result<字源上下文, 错误>) -> Result<(), 错误> {
+    let 问题列表 = match 加载结果 {
+        Ok(上下文) => 上下文.校验(),
+        // 加载时收集的问题每行一项，去掉“有 N 处问题”的标题行后逐项计数
+        Err(e) => e
+            .message
+            .lines()
+            .filter(|x| !x.ends_with("处问题："))
+            .map(|x| x.to_string())
+            .collect(),
+    };
+    if 问题列表.is_empty() {
+        println!("校验通过");
+        return Ok(());
+    }
+    for 问题 in &问题列表 {
+        println!("{问题}");
+    }
+    Err(错误::from(format!(
+        "校验未通过，共 {} 项问题",
+        问题列表.len()
+    )))
+}
 
 fn main() -> Result<(), 错误> {
-    let 参数 = 默认命令行参数::parse();
+    let 字源参数 = 字源命令行参数::parse();
+    let 参数 = 字源参数.默认参数();
     let 输入 = 从命令行参数创建(&参数);
-    let 上下文 = 字源上下文::新建(输入)?;
-    let _config = 上下文.配置.clone();
-    match 参数.clone().command {
-        命令::Encode { data: _ } => {
+    let 字源配置 = 字源配置::读取(&字源参数.配置路径())?;
+    let 加载结果 = 字源上下文::新建(输入, 字源配置);
+    if let 字源命令::Validate { .. } = 字源参数.command {
+        return 输出校验报告(加载结果);
+    }
+    let 上下文 = 加载结果?;
+    match 字源参数.command {
+        字源命令::Encode { .. } => {
             let 编码器 = 字源编码器::新建(&上下文)?;
             let mut 目标函数 = 字源目标函数::新建(&上下文, 编码器);
             let (指标, 分数) = 目标函数.计算(&上下文.初始决策, &None);
             println!("分数：{分数:.4}");
+            if 上下文.字源配置.encoder.简码分配 == 字源简码分配::最优 {
+                let mut 贪心编码器 = 字源编码器::新建(&上下文)?;
+                贪心编码器.简码分配 = 字源简码分配::贪心;
+                let mut 贪心目标函数 = 字源目标函数::新建(&上下文, 贪心编码器);
+                let (贪心指标, _) = 贪心目标函数.计算(&上下文.初始决策, &None);
+                println!(
+                    "一字简码码长：贪心分配 {:.4}，最优分配 {:.4}，减少 {:.4}",
+                    贪心指标.一字简码码长,
+                    指标.一字简码码长,
+                    贪心指标.一字简码码长 - 指标.一字简码码长
+                );
+            }
             let 码表 = 目标函数.编码器.生成码表();
             let 命令行 = 命令行::新建(参数, None);
             命令行.输出编码结果(码表);
             命令行.输出评测指标(指标);
         }
-        命令::Optimize { data: _, threads } => {
+        字源命令::Optimize {
+            threads, resume, ..
+        } => {
             let 命令行 = 命令行::新建(参数, None);
-            let SolverConfig::SimulatedAnnealing(退火) =
-                _config.optimization.unwrap().metaheuristic.unwrap();
+            let 求解器 = 字源求解器::新建(&上下文)?;
+            if resume.len() > threads {
+                return Err(错误::from(format!(
+                    "续优化文件有 {} 个，多于线程数 {threads}，以下文件不会被使用：{:?}",
+                    resume.len(),
+                    &resume[threads..]
+                )));
+            }
+            let mut 初始决策列表 = vec![];
+            for 路径 in &resume {
+                初始决策列表.push(上下文.读取决策(路径)?);
+            }
+            if 初始决策列表.is_empty() {
+                初始决策列表.push(上下文.初始决策.clone());
+            }
             let mut 线程池 = vec![];
             for 线程序号 in 0..threads {
                 let 编码器 = 字源编码器::新建(&上下文)?;
                 let mut 目标函数 = 字源目标函数::新建(&上下文, 编码器);
                 let mut 操作 = 字源操作::新建(&上下文);
-                let 优化方法 = 退火.clone();
+                let 优化方法 = 求解器.clone();
                 let 上下文 = 上下文.clone();
+                let 初始决策 = 初始决策列表[线程序号 % 初始决策列表.len()].clone();
                 let 子命令行 = 命令行.生成子命令行(线程序号);
                 let 线程 = spawn(move || {
-                    let 优化结果 = 优化方法.优化(
-                        &上下文.初始决策,
-                        &mut 目标函数,
-                        &mut 操作,
-                        &上下文,
-                        &子命令行,
-                    );
+                    let 优化结果 =
+                        优化方法.优化(&初始决策, &mut 目标函数, &mut 操作, &上下文, &子命令行);
                     目标函数.计算(&优化结果.映射, &None);
                     let 码表 = 目标函数.编码器.生成码表();
                     let 分析路径 = 子命令行.输出目录.join("分析.md");
@@ -83,7 +143,25 @@ fn main() -> Result<(), 错误> {
                 )?;
             }
         }
-        _ => {}
+        字源命令::Export {
+            output,
+            name,
+            format,
+            ..
+        } => {
+            let 编码器 = 字源编码器::新建(&上下文)?;
+            let mut 目标函数 = 字源目标函数::新建(&上下文, 编码器);
+            目标函数.计算(&上下文.初始决策, &None);
+            let 码表 = 目标函数.编码器.生成码表();
+            let 导出列表 = 收集导出项(&码表, &目标函数.编码器.编码结果);
+            输出码表(format, &上下文, &导出列表, &name, &output)?;
+            println!("已导出 {} 条到 {output:?}", 导出列表.len());
+        }
+        字源命令::Chart { output, .. } => {
+            输出字根图(&上下文, &上下文.初始决策, &output)?;
+            println!("已输出字根图到 {output:?}");
+        }
+        字源命令::Validate { .. } => {}
     }
     Ok(())
 }
@@ -1,15 +1,38 @@
+use crate::config::{字源分级权重, 字源目标权重};
 use crate::context::{
-    字源上下文, 字源元素安排, 字源决策, 字源决策变化, 字源决策空间, 最大码长, 进制
+    字源上下文, 字源元素安排, 字源决策, 字源决策变化, 字源决策空间, 码制
 };
 use crate::encoder::字源编码器;
 use chai::encoders::编码器;
-use chai::{objectives::目标函数, 棱镜, 键位分布信息};
+use chai::{objectives::目标函数, 棱镜, 编码信息, 键位分布信息};
 use rustc_hash::FxHashMap;
 use serde::Serialize;
 use std::{fmt::Display, iter::zip};
 
-const _分级数: usize = 5;
-const _分级大小: [usize; _分级数] = [1500, 3000, 4500, 6000, usize::MAX];
+const 分级数: usize = 5;
+const 分级大小: [usize; 分级数] = [1500, 3000, 4500, 6000, usize::MAX];
+/// 多字词按字数分组统计，最后一组也包含更长的词
+const 词长分组: [usize; 3] = [2, 3, 4];
+
+fn 词长组(词长: usize) -> usize {
+    词长.clamp(词长分组[0], 词长分组[词长分组.len() - 1]) - 词长分组[0]
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct 字源词长指标 {
+    pub 词长: usize,
+    pub 选重数: u64,
+    pub 选重率: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct 字源分级指标 {
+    pub 前: usize,
+    pub 选重数: u64,
+    pub 选重率: f64,
+    pub 码长: f64,
+    pub 组合当量: f64,
+}
 
 #[derive(Debug, Clone, Serialize)]
 pub struct 字源指标 {
@@ -21,9 +44,12 @@ pub struct 字源指标 {
     pub 一字简码选重率: f64,
     pub 多字全码选重数: u64,
     pub 多字全码选重率: f64,
+    pub 词长指标: Vec<字源词长指标>,
     pub 组合当量: f64,
     pub 按键分布: FxHashMap<char, f64>,
     pub 按键分布偏差: f64,
+    pub 打分损失: f64,
+    pub 分级指标: Vec<字源分级指标>,
 }
 
 impl Display for 字源指标 {
@@ -43,12 +69,27 @@ impl Display for 字源指标 {
             self.多字全码选重数,
             self.多字全码选重率 * 100.0
         )?;
+        for 指标 in &self.词长指标 {
+            let 词长 = if 指标.词长 == 词长分组[词长分组.len() - 1] {
+                format!("{}+", 指标.词长)
+            } else {
+                指标.词长.to_string()
+            };
+            write!(
+                f,
+                "{}字词全码选重数：{}；选重率：{:.2}%\n",
+                词长,
+                指标.选重数,
+                指标.选重率 * 100.0
+            )?;
+        }
         write!(
             f,
-            "一字简码码长：{:.4}；组合当量：{:.2}%；按键分布偏差：{:.2}%；按键分布：",
+            "一字简码码长：{:.4}；组合当量：{:.2}%；按键分布偏差：{:.2}%；打分损失：{:.2}；按键分布：",
             self.一字简码码长,
             self.组合当量 * 100.0,
-            self.按键分布偏差 * 100.0
+            self.按键分布偏差 * 100.0,
+            self.打分损失
         )?;
         for 行 in chai::objectives::metric::键盘布局.iter() {
             if 行.iter().any(|x| self.按键分布.contains_key(x)) {
@@ -62,7 +103,192 @@ impl Display for 字源指标 {
                 f.write_str(&buffer.join(" | "))?;
             }
         }
-        f.write_str("\n")
+        f.write_str("\n")?;
+        for 分级 in &self.分级指标 {
+            let 范围 = if 分级.前 == usize::MAX {
+                "全部".to_string()
+            } else {
+                format!("前 {} ", 分级.前)
+            };
+            write!(
+                f,
+                "{}字：全码选重数：{}；全码选重率：{:.2}%；简码码长：{:.4}；组合当量：{:.2}%\n",
+                范围,
+                分级.选重数,
+                分级.选重率 * 100.0,
+                分级.码长,
+                分级.组合当量 * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 各项指标的累计量，增量计算时对发生变化的词先减去旧贡献、再加上新贡献
+#[derive(Debug, Clone)]
+struct 字源累计 {
+    一字总频率: i64,
+    多字总频率: i64,
+    一字总键数: i64,
+    一字全码选重数: i64,
+    一字全码选重频率: i64,
+    一字简码选重数: i64,
+    一字简码选重频率: i64,
+    多字全码选重数: i64,
+    多字全码选重频率: i64,
+    词长总频率: Vec<i64>,
+    词长选重数: Vec<i64>,
+    词长选重频率: Vec<i64>,
+    总组合数: i64,
+    总组合当量: f64,
+    按键数向量: Vec<i64>,
+    总键数: i64,
+    分级总频率: Vec<i64>,
+    分级总键数: Vec<i64>,
+    分级选重数: Vec<i64>,
+    分级选重频率: Vec<i64>,
+    分级组合数: Vec<i64>,
+    分级组合当量: Vec<f64>,
+}
+
+impl 字源累计 {
+    fn 新建(分级个数: usize, 进制: u64) -> Self {
+        Self {
+            一字总频率: 0,
+            多字总频率: 0,
+            一字总键数: 0,
+            一字全码选重数: 0,
+            一字全码选重频率: 0,
+            一字简码选重数: 0,
+            一字简码选重频率: 0,
+            多字全码选重数: 0,
+            多字全码选重频率: 0,
+            词长总频率: vec![0; 词长分组.len()],
+            词长选重数: vec![0; 词长分组.len()],
+            词长选重频率: vec![0; 词长分组.len()],
+            总组合数: 0,
+            总组合当量: 0.0,
+            按键数向量: vec![0; 进制 as usize],
+            总键数: 0,
+            分级总频率: vec![0; 分级个数],
+            分级总键数: vec![0; 分级个数],
+            分级选重数: vec![0; 分级个数],
+            分级选重频率: vec![0; 分级个数],
+            分级组合数: vec![0; 分级个数],
+            分级组合当量: vec![0.0; 分级个数],
+        }
+    }
+
+    /// 整数量完全相同，浮点量在舍入误差范围内相同
+    fn 近似相等(&self, 其他: &Self) -> bool {
+        let 整数 = |x: &Self| {
+            (
+                [
+                    x.一字总频率,
+                    x.多字总频率,
+                    x.一字总键数,
+                    x.一字全码选重数,
+                    x.一字全码选重频率,
+                    x.一字简码选重数,
+                    x.一字简码选重频率,
+                    x.多字全码选重数,
+                    x.多字全码选重频率,
+                    x.总组合数,
+                    x.总键数,
+                ],
+                [
+                    x.词长总频率.clone(),
+                    x.词长选重数.clone(),
+                    x.词长选重频率.clone(),
+                    x.按键数向量.clone(),
+                    x.分级总频率.clone(),
+                    x.分级总键数.clone(),
+                    x.分级选重数.clone(),
+                    x.分级选重频率.clone(),
+                    x.分级组合数.clone(),
+                ],
+            )
+        };
+        let 浮点 = |x: &Self| {
+            let mut 列表 = vec![x.总组合当量];
+            列表.extend_from_slice(&x.分级组合当量);
+            列表
+        };
+        整数(self) == 整数(其他)
+            && zip(浮点(self), 浮点(其他))
+                .all(|(a, b)| (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1.0))
+    }
+
+    /// 符号为 1 时加上一个词的贡献，为 -1 时减去
+    fn 累加(
+        &mut self,
+        编码信息: &编码信息,
+        一字排名: usize,
+        当量信息: &[f64],
+        分级上限: &[usize],
+        码制: &码制,
+        符号: i64,
+    ) {
+        let 频率 = 编码信息.频率 as i64 * 符号;
+        let 预测实际打法 = if 编码信息.词长 == 1 {
+            编码信息.简码.实际编码
+        } else {
+            编码信息.全码.实际编码
+        };
+        let 编码长度 = if 编码信息.词长 == 1 {
+            码制.码长(预测实际打法) as i64
+        } else {
+            码制.最大码长 as i64
+        };
+        let 组合当量 = 频率 as f64 * 当量信息[预测实际打法 as usize];
+        if 编码信息.词长 == 1 {
+            self.一字总频率 += 频率;
+            self.一字总键数 += 频率 * 编码长度;
+        } else {
+            self.多字总频率 += 频率;
+            self.词长总频率[词长组(编码信息.词长)] += 频率;
+        }
+        if 编码信息.全码.选重标记 {
+            if 编码信息.词长 == 1 {
+                self.一字全码选重数 += 符号;
+                self.一字全码选重频率 += 频率;
+            } else {
+                self.多字全码选重数 += 符号;
+                self.多字全码选重频率 += 频率;
+                self.词长选重数[词长组(编码信息.词长)] += 符号;
+                self.词长选重频率[词长组(编码信息.词长)] += 频率;
+            }
+        }
+        if 编码信息.简码.选重标记 {
+            if 编码信息.词长 == 1 {
+                self.一字简码选重数 += 符号;
+                self.一字简码选重频率 += 频率;
+            }
+        }
+        self.总键数 += 频率 * 编码长度;
+        self.总组合数 += 频率 * (编码长度 - 1);
+        self.总组合当量 += 组合当量;
+        if 编码信息.词长 == 1 {
+            for (分级序号, 上限) in 分级上限.iter().enumerate() {
+                if 一字排名 >= *上限 {
+                    continue;
+                }
+                self.分级总频率[分级序号] += 频率;
+                self.分级总键数[分级序号] += 频率 * 编码长度;
+                self.分级组合数[分级序号] += 频率 * (编码长度 - 1);
+                self.分级组合当量[分级序号] += 组合当量;
+                if 编码信息.全码.选重标记 {
+                    self.分级选重数[分级序号] += 符号;
+                    self.分级选重频率[分级序号] += 频率;
+                }
+            }
+        }
+        let mut 剩余编码 = 预测实际打法;
+        while 剩余编码 > 0 {
+            let 键 = 剩余编码 % 码制.进制;
+            self.按键数向量[键 as usize] += 频率;
+            剩余编码 /= 码制.进制;
+        }
     }
 }
 
@@ -72,21 +298,143 @@ pub struct 字源目标函数 {
     pub 键位分布信息: 键位分布信息,
     pub 棱镜: 棱镜,
     pub 决策空间: 字源决策空间,
+    pub 最高打分: Vec<f64>,
+    pub 权重: 字源目标权重,
+    pub 分级: Vec<字源分级权重>,
+    分级上限: Vec<usize>,
+    /// 每个词在单字中的频率排名，多字词为 usize::MAX
+    一字排名: Vec<usize>,
+    累计: 字源累计,
+    /// 上一次计算时的编码结果，增量计算时用来减去旧贡献
+    上次编码结果: Vec<编码信息>,
+    计算次数: usize,
+    全量重算间隔: usize,
 }
 
 impl 字源目标函数 {
     pub fn 新建(上下文: &字源上下文, 编码器: 字源编码器) -> Self {
         let 当量信息 = 上下文
             .棱镜
-            .预处理当量信息(&上下文.原始当量信息, 进制.pow(最大码长 as u32) as usize);
+            .预处理当量信息(&上下文.原始当量信息, 上下文.码制.编码空间大小());
         let 键位分布信息 = 上下文.棱镜.预处理键位分布信息(&上下文.原始键位分布信息);
+        let 权重 = 上下文.字源配置.optimization.objective.clone();
+        let 分级 = if 权重.分级.is_empty() {
+            分级大小
+                .iter()
+                .map(|&前| 字源分级权重 {
+                    前: Some(前),
+                    ..Default::default()
+                })
+                .collect()
+        } else {
+            权重.分级.clone()
+        };
+        let 分级上限: Vec<_> = 分级
+            .iter()
+            .map(|x: &字源分级权重| x.前.unwrap_or(usize::MAX))
+            .collect();
+        let 最高打分 = 上下文
+            .决策空间
+            .元素
+            .iter()
+            .map(|安排列表| {
+                安排列表
+                    .iter()
+                    .map(|x| x.打分)
+                    .fold(f64::NEG_INFINITY, f64::max)
+            })
+            .collect();
+        // 编码结果按频率降序排列
+        let mut 一字排名 = vec![];
+        let mut 一字序号 = 0;
+        for 编码信息 in &编码器.编码结果 {
+            if 编码信息.词长 == 1 {
+                一字排名.push(一字序号);
+                一字序号 += 1;
+            } else {
+                一字排名.push(usize::MAX);
+            }
+        }
+        let 累计 = 字源累计::新建(分级上限.len(), 上下文.码制.进制);
         Self {
             编码器,
             当量信息,
             键位分布信息,
             棱镜: 上下文.棱镜.clone(),
             决策空间: 上下文.决策空间.clone(),
+            最高打分,
+            权重,
+            分级,
+            分级上限,
+            一字排名,
+            累计,
+            上次编码结果: vec![],
+            计算次数: 0,
+            全量重算间隔: 上下文.字源配置.optimization.全量重算间隔,
+        }
+    }
+
+    fn 全量累计(&mut self) {
+        let mut 累计 = 字源累计::新建(self.分级上限.len(), self.编码器.码制.进制);
+        for (序号, 编码信息) in self.编码器.编码结果.iter().enumerate() {
+            累计.累加(
+                编码信息,
+                self.一字排名[序号],
+                &self.当量信息,
+                &self.分级上限,
+                &self.编码器.码制,
+                1,
+            );
+        }
+        self.累计 = 累计;
+        self.上次编码结果 = self.编码器.编码结果.clone();
+    }
+
+    fn 增量累计(&mut self) {
+        for 序号 in &self.编码器.变化词 {
+            let 旧编码信息 = &self.上次编码结果[*序号];
+            let 新编码信息 = &self.编码器.编码结果[*序号];
+            let 一字排名 = self.一字排名[*序号];
+            self.累计.累加(
+                旧编码信息,
+                一字排名,
+                &self.当量信息,
+                &self.分级上限,
+                &self.编码器.码制,
+                -1,
+            );
+            self.累计.累加(
+                新编码信息,
+                一字排名,
+                &self.当量信息,
+                &self.分级上限,
+                &self.编码器.码制,
+                1,
+            );
+            self.上次编码结果[*序号] = 新编码信息.clone();
+        }
+    }
+
+    /// 调试构建中在定期全量重算时对照增量结果：编码器的增量编码要与从头编码一致，
+    /// 增量累计要与全量累计一致
+    fn 校验增量结果(&mut self, 解: &字源决策) {
+        let mut 全量编码器 = self.编码器.clone();
+        全量编码器.编码(解, &None, &mut vec![]);
+        let 摘要 = |x: &编码信息| {
+            [&x.全码, &x.简码].map(|y| (y.原始编码, y.实际编码, y.原始编码候选位置, y.选重标记))
+        };
+        for (序号, (增量, 全量)) in zip(&self.编码器.编码结果, &全量编码器.编码结果).enumerate()
+        {
+            assert_eq!(
+                摘要(增量),
+                摘要(全量),
+                "第 {序号} 个词的增量编码与从头编码不一致"
+            );
         }
+        self.增量累计();
+        let 增量累计 = self.累计.clone();
+        self.全量累计();
+        assert!(增量累计.近似相等(&self.累计), "增量累计与全量累计不一致");
     }
 }
 
@@ -99,70 +447,41 @@ impl 目标函数 for 字源目标函数 {
         &mut self, 解: &字源决策, 变化: &Option<字源决策变化>
     ) -> (字源指标, f64) {
         self.编码器.编码(解, 变化, &mut vec![]);
-        let 长度分界点 = [0, 1, 2, 3, 4].map(|x| 进制.pow(x));
-        let mut 一字总频率 = 0;
-        let mut 多字总频率 = 0;
-        let mut 一字总键数 = 0;
-        let mut 一字全码选重数 = 0;
-        let mut 一字全码选重频率 = 0;
-        let mut 一字简码选重数 = 0;
-        let mut 一字简码选重频率 = 0;
-        let mut 多字全码选重数 = 0;
-        let mut 多字全码选重频率 = 0;
-        let mut 总组合数 = 0;
-        let mut 总组合当量 = 0.0;
-        let mut 按键数向量 = vec![0; 进制 as usize];
-        let mut 总键数 = 0;
-        for 编码信息 in self.编码器.编码结果.iter() {
-            let 预测实际打法 = if 编码信息.词长 == 1 {
-                编码信息.简码.实际编码
-            } else {
-                编码信息.全码.实际编码
-            };
-            let 编码长度 = if 编码信息.词长 == 1 {
-                长度分界点.iter().position(|&x| 预测实际打法 < x).unwrap() as u64
-            } else {
-                4
-            };
-            if 编码信息.词长 == 1 {
-                一字总频率 += 编码信息.频率;
-                一字总键数 += 编码信息.频率 * 编码长度;
-            } else {
-                多字总频率 += 编码信息.频率;
-            }
-            if 编码信息.全码.选重标记 {
-                if 编码信息.词长 == 1 {
-                    一字全码选重数 += 1;
-                    一字全码选重频率 += 编码信息.频率;
-                } else {
-                    多字全码选重数 += 1;
-                    多字全码选重频率 += 编码信息.频率;
-                }
-            }
-            if 编码信息.简码.选重标记 {
-                if 编码信息.词长 == 1 {
-                    一字简码选重数 += 1;
-                    一字简码选重频率 += 编码信息.频率;
-                }
-            }
-            总键数 += 编码信息.频率 * 编码长度;
-            总组合数 += 编码信息.频率 * (编码长度 - 1);
-            总组合当量 += 编码信息.频率 as f64 * self.当量信息[预测实际打法 as usize];
-            let mut 剩余编码 = 预测实际打法;
-            while 剩余编码 > 0 {
-                let 键 = 剩余编码 % 进制;
-                按键数向量[键 as usize] += 编码信息.频率;
-                剩余编码 /= 进制;
-            }
+        self.计算次数 += 1;
+        // 浮点累计量会有误差，每隔一定次数全量重算一次
+        let 可增量 = !self.编码器.全部变化 && !self.上次编码结果.is_empty();
+        if 可增量 && self.全量重算间隔 > 1 && self.计算次数 % self.全量重算间隔 != 0
+        {
+            self.增量累计();
+        } else if 可增量 && cfg!(debug_assertions) {
+            self.校验增量结果(解);
+        } else {
+            self.全量累计();
         }
+        let 累计 = &self.累计;
 
-        let 字根数 = self.决策空间.字根
+        let 字根数 = self
+            .决策空间
+            .字根
             .iter()
             .filter(|&x| &解.元素[*x] != &字源元素安排::未选取)
             .count();
-        let 分布: Vec<_> = 按键数向量
+        // 打分损失要遍历全部安排，权重为 0 时不计算
+        let mut 打分损失 = 0.0;
+        if self.权重.打分损失 != 0.0 {
+            for (元素, 安排列表) in self.决策空间.元素.iter().enumerate() {
+                let 所选安排 = 安排列表
+                    .iter()
+                    .find(|x| x.安排 == 解.元素[元素] && 解.允许(x));
+                if let Some(所选安排) = 所选安排 {
+                    打分损失 += self.最高打分[元素] - 所选安排.打分;
+                }
+            }
+        }
+        let 分布: Vec<_> = 累计
+            .按键数向量
             .iter()
-            .map(|x| *x as f64 / 总键数 as f64)
+            .map(|x| *x as f64 / 累计.总键数 as f64)
             .collect();
         let mut 按键分布偏差 = 0.0;
         for (frequency, loss) in zip(&分布, &self.键位分布信息) {
@@ -174,19 +493,49 @@ impl 目标函数 for 字源目标函数 {
             }
         }
         let mut 按键分布 = FxHashMap::default();
-        for (键, 频率) in 按键数向量.iter().enumerate() {
+        for (键, 频率) in 累计.按键数向量.iter().enumerate() {
             if let Some(键) = self.棱镜.数字转键.get(&(键 as u64)) {
-                按键分布.insert(*键, *频率 as f64 / 总键数 as f64);
+                按键分布.insert(*键, *频率 as f64 / 累计.总键数 as f64);
             }
         }
-        let 一字全码选重率 = 一字全码选重频率 as f64 / 一字总频率 as f64;
+        let 一字总频率 = 累计.一字总频率 as f64;
+        let 一字全码选重数 = 累计.一字全码选重数 as u64;
+        let 一字简码选重数 = 累计.一字简码选重数 as u64;
+        let 多字全码选重数 = 累计.多字全码选重数 as u64;
+        let 一字全码选重率 = 累计.一字全码选重频率 as f64 / 一字总频率;
         let 一字全码静态选重率 = 一字全码选重数 as f64 / self.编码器.一字信息.len() as f64;
-        let 一字简码选重率 = 一字简码选重频率 as f64 / 一字总频率 as f64;
+        let 一字简码选重率 = 累计.一字简码选重频率 as f64 / 一字总频率;
         let 一字简码静态选重率 = 一字简码选重数 as f64 / self.编码器.一字信息.len() as f64;
-        let 多字全码选重率 = 多字全码选重频率 as f64 / 多字总频率 as f64;
+        let 多字全码选重率 = 累计.多字全码选重频率 as f64 / 累计.多字总频率 as f64;
         let 多字全码静态选重率 = 多字全码选重数 as f64 / self.编码器.多字信息.len() as f64;
-        let 组合当量 = 总组合当量 / 总组合数 as f64;
-        let 一字简码码长 = 一字总键数 as f64 / 一字总频率 as f64;
+        let 词长指标 = 词长分组
+            .iter()
+            .enumerate()
+            .filter(|(组, _)| 累计.词长总频率[*组] > 0)
+            .map(|(组, 词长)| 字源词长指标 {
+                词长: *词长,
+                选重数: 累计.词长选重数[组] as u64,
+                选重率: 累计.词长选重频率[组] as f64 / 累计.词长总频率[组] as f64,
+            })
+            .collect();
+        let 组合当量 = 累计.总组合当量 / 累计.总组合数 as f64;
+        let 一字简码码长 = 累计.一字总键数 as f64 / 一字总频率;
+        let mut 分级指标 = vec![];
+        let mut 分级目标函数值 = 0.0;
+        for (分级序号, 分级权重) in self.分级.iter().enumerate() {
+            let 总频率 = 累计.分级总频率[分级序号] as f64;
+            let 指标 = 字源分级指标 {
+                前: self.分级上限[分级序号],
+                选重数: 累计.分级选重数[分级序号] as u64,
+                选重率: 累计.分级选重频率[分级序号] as f64 / 总频率,
+                码长: 累计.分级总键数[分级序号] as f64 / 总频率,
+                组合当量: 累计.分级组合当量[分级序号] / 累计.分级组合数[分级序号] as f64,
+            };
+            分级目标函数值 += 指标.选重率 * 分级权重.选重率
+                + 指标.码长 * 分级权重.码长
+                + 指标.组合当量 * 分级权重.组合当量;
+            分级指标.push(指标);
+        }
         let 指标 = 字源指标 {
             字根数,
             一字简码码长,
@@ -196,20 +545,26 @@ impl 目标函数 for 字源目标函数 {
             一字简码选重率,
             多字全码选重数,
             多字全码选重率,
+            词长指标,
             组合当量,
             按键分布,
             按键分布偏差,
+            打分损失,
+            分级指标,
         };
-        let 目标函数值 = 一字全码选重率 * 1.0
-            + 一字全码静态选重率 * 0.3
-            + 一字简码选重率 * 0.5
-            + 一字简码静态选重率 * 0.1
-            + 多字全码选重率 * 0.3
-            + 多字全码静态选重率 * 0.03
-            + 组合当量 * 0.5
-            + 按键分布偏差 * 0.01
-            + 一字简码码长 * 0.01
-            + 字根数 as f64 * 0.00003;
+        let 权重 = &self.权重;
+        let 目标函数值 = 一字全码选重率 * 权重.一字全码选重率
+            + 一字全码静态选重率 * 权重.一字全码静态选重率
+            + 一字简码选重率 * 权重.一字简码选重率
+            + 一字简码静态选重率 * 权重.一字简码静态选重率
+            + 多字全码选重率 * 权重.多字全码选重率
+            + 多字全码静态选重率 * 权重.多字全码静态选重率
+            + 组合当量 * 权重.组合当量
+            + 按键分布偏差 * 权重.按键分布偏差
+            + 一字简码码长 * 权重.一字简码码长
+            + 字根数 as f64 * 权重.字根数
+            + 打分损失 * 权重.打分损失
+            + 分级目标函数值;
 
         (指标, 目标函数值)
     }
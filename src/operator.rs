@@ -1,31 +1,46 @@
+use crate::config::字源操作配置;
 use crate::context::{
-    字源上下文, 字源元素安排, 字源决策, 字源决策变化, 字源决策空间
+    字源上下文, 字源元素安排, 字源决策, 字源决策变化, 字源决策空间, 字源条件元素安排,
 };
 use chai::{operators::变异, 元素, 棱镜};
-use rand::{random, random_range, rng, seq::IndexedRandom};
-use rustc_hash::FxHashMap;
-use std::collections::VecDeque;
+use rand::{random, rng, seq::IndexedRandom};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{collections::VecDeque, iter::zip};
 
-const 最大尝试次数: usize = 100;
+/// 产生字根、湮灭字根、移动字根、交换字根、轮换字根
+const 操作数: usize = 5;
 
 pub struct 字源操作 {
     _棱镜: 棱镜,
     决策空间: 字源决策空间,
     下游字根: FxHashMap<元素, Vec<元素>>,
+    配置: 字源操作配置,
+    基础概率: [f64; 操作数],
+    概率: [f64; 操作数],
+    接受率: [f64; 操作数],
+    上次变异: Option<(usize, Vec<(元素, 字源元素安排)>)>,
+    /// 由优化方法通过 更新接受率 告知变异是否被接受，不再从决策推断
+    显式统计: bool,
 }
 
 impl 变异 for 字源操作 {
     type 决策 = 字源决策;
     fn 变异(&mut self, 决策: &mut 字源决策) -> 字源决策变化 {
-        let 随机数: f64 = random();
-        let mut 变化 = if 随机数 < 0.2 {
-            self.产生字根(决策)
-        } else if 随机数 < 0.4 {
-            self.湮灭字根(决策)
-        } else {
-            self.移动字根(决策)
+        if self.配置.自适应 && !self.显式统计 {
+            self.统计接受(决策);
+        }
+        let 操作序号 = self.选择操作();
+        let mut 变化 = match 操作序号 {
+            0 => self.产生字根(决策),
+            1 => self.湮灭字根(决策),
+            2 => self.移动字根(决策),
+            3 => self.轮换(决策, 2),
+            _ => self.轮换(决策, self.配置.轮换数量.max(2)),
         };
         self.传播(&mut 变化, 决策);
+        if self.配置.自适应 {
+            self.记录变异(操作序号, &变化, 决策);
+        }
         变化
     }
 }
@@ -35,11 +50,110 @@ impl 字源操作 {
         let 棱镜 = 上下文.棱镜.clone();
         let 决策空间 = 上下文.决策空间.clone();
         let 下游字根 = 上下文.元素图.clone();
+        let 配置 = 上下文.字源配置.optimization.operator.clone();
+        let 相对概率 = [
+            配置.产生字根,
+            配置.湮灭字根,
+            配置.移动字根,
+            配置.交换字根,
+            配置.轮换字根,
+        ];
+        let 总和: f64 = 相对概率.iter().sum();
+        let 基础概率 = 相对概率.map(|x| x / 总和);
         return 字源操作 {
             _棱镜: 棱镜,
             决策空间,
             下游字根,
+            配置,
+            基础概率,
+            概率: 基础概率,
+            接受率: [1.0; 操作数],
+            上次变异: None,
+            显式统计: false,
+        };
+    }
+
+    fn 权重(&self, 条件安排: &字源条件元素安排) -> f64 {
+        (self.配置.打分偏好 * 条件安排.打分).exp()
+    }
+
+    fn 选择操作(&self) -> usize {
+        let mut 随机数: f64 = random();
+        for (操作序号, 概率) in self.概率.iter().enumerate() {
+            if 随机数 < *概率 {
+                return 操作序号;
+            }
+            随机数 -= 概率;
+        }
+        // 浮点误差导致没有选中时，取最后一个启用的操作
+        self.概率
+            .iter()
+            .rposition(|x| *x > 0.0)
+            .unwrap_or(操作数 - 1)
+    }
+
+    fn 记录变异(
+        &mut self, 操作序号: usize, 变化: &字源决策变化, 决策: &字源决策
+    ) {
+        let 结果: Vec<_> = 变化
+            .增加字根
+            .iter()
+            .chain(变化.减少字根.iter())
+            .chain(变化.移动字根.iter())
+            .map(|x| (*x, 决策.元素[*x]))
+            .collect();
+        self.上次变异 = if 结果.is_empty() {
+            None
+        } else {
+            Some((操作序号, 结果))
+        };
+    }
+
+    /// 一次生成多个候选再从中挑选的优化方法无法通过决策推断接受情况，
+    /// 需要改为在挑选后调用 更新接受率
+    pub fn 启用显式统计(&mut self) {
+        self.显式统计 = true;
+    }
+
+    /// 上一次产生了变化的变异所用的操作，只在自适应时记录
+    pub fn 上次操作(&self) -> Option<usize> {
+        self.上次变异.as_ref().map(|x| x.0)
+    }
+
+    /// 优化方法不会告知变异是否被接受，但被拒绝的变异会被撤销，
+    /// 所以本次传入的决策如果还保持着上次变异的结果，就说明上次变异被接受了
+    fn 统计接受(&mut self, 决策: &字源决策) {
+        let Some((操作序号, 结果)) = self.上次变异.take() else {
+            return;
         };
+        let 接受 = 结果.iter().all(|(元素, 安排)| 决策.元素[*元素] == *安排);
+        self.更新接受率(操作序号, 接受);
+    }
+
+    pub fn 更新接受率(&mut self, 操作序号: usize, 接受: bool) {
+        if !self.配置.自适应 {
+            return;
+        }
+        let 学习率 = self.配置.自适应学习率;
+        self.接受率[操作序号] =
+            (1.0 - 学习率) * self.接受率[操作序号] + if 接受 { 学习率 } else { 0.0 };
+        let 权重: Vec<_> = zip(&self.基础概率, &self.接受率)
+            .map(|(概率, 接受率)| 概率 * 接受率)
+            .collect();
+        let 总权重: f64 = 权重.iter().sum();
+        if 总权重 <= 0.0 {
+            return;
+        }
+        // 概率为 0 的操作视为被禁用，其余操作各保留最小概率
+        let 启用数 = self.基础概率.iter().filter(|x| **x > 0.0).count();
+        let 最小概率 = self.配置.最小概率.min(1.0 / 启用数 as f64);
+        for 操作序号 in 0..操作数 {
+            self.概率[操作序号] = if self.基础概率[操作序号] > 0.0 {
+                最小概率 + (1.0 - 最小概率 * 启用数 as f64) * 权重[操作序号] / 总权重
+            } else {
+                0.0
+            };
+        }
     }
 
     fn 传播(&self, 变化: &mut 字源决策变化, 决策: &mut 字源决策) {
@@ -78,7 +192,7 @@ impl 字源操作 {
                         合法 = true;
                         break;
                     }
-                    新安排列表.push(条件安排.安排.clone());
+                    新安排列表.push(条件安排);
                 }
             }
             if !合法 {
@@ -89,15 +203,18 @@ impl 字源操作 {
                         self.决策空间.元素[元素]
                     );
                 } else {
-                    let 新安排 = 新安排列表.choose(&mut rng()).unwrap();
+                    let 新安排 = 新安排列表
+                        .choose_weighted(&mut rng(), |x| self.权重(x))
+                        .unwrap()
+                        .安排;
                     if 决策.元素[元素] == 字源元素安排::未选取 {
                         变化.增加字根.push(元素);
-                    } else if 新安排 == &字源元素安排::未选取 {
+                    } else if 新安排 == 字源元素安排::未选取 {
                         变化.减少字根.push(元素);
                     } else {
                         变化.移动字根.push(元素);
                     }
-                    决策.元素[元素] = 新安排.clone();
+                    决策.元素[元素] = 新安排;
                 }
             }
             for 下游元素 in self.下游字根.get(&元素).unwrap_or(&vec![]) {
@@ -110,19 +227,20 @@ impl 字源操作 {
 
     fn 产生字根(&self, 决策: &mut 字源决策) -> 字源决策变化 {
         let mut rng = rng();
-        for _ in 0..最大尝试次数 {
+        for _ in 0..self.配置.最大尝试次数 {
             let 元素 = *self.决策空间.字根.choose(&mut rng).unwrap();
             if 决策.元素[元素] != 字源元素安排::未选取 {
                 continue;
             }
-            // 蓄水池抽样
+            // 按打分加权的蓄水池抽样
             let mut 下一个安排 = None;
-            let mut count = 0;
+            let mut 总权重 = 0.0;
             for 条件安排 in &self.决策空间.元素[元素] {
                 if 条件安排.安排 != 字源元素安排::未选取 && 决策.允许(条件安排)
                 {
-                    count += 1;
-                    if random_range(0..count) == 0 {
+                    let 权重 = self.权重(条件安排);
+                    总权重 += 权重;
+                    if random::<f64>() * 总权重 < 权重 {
                         下一个安排 = Some(条件安排.安排);
                     }
                 }
@@ -137,7 +255,7 @@ impl 字源操作 {
 
     fn 湮灭字根(&self, 决策: &mut 字源决策) -> 字源决策变化 {
         let mut rng = rng();
-        for _ in 0..最大尝试次数 {
+        for _ in 0..self.配置.最大尝试次数 {
             let 元素 = *self.决策空间.字根.choose(&mut rng).unwrap();
             if 决策.元素[元素] == 字源元素安排::未选取 {
                 continue;
@@ -146,7 +264,7 @@ impl 字源操作 {
                 if 条件安排.安排 == 字源元素安排::未选取 && 决策.允许(条件安排)
                 {
                     决策.元素[元素] = 条件安排.安排;
-                    return 字源决策变化::新建(vec![], vec![], vec![元素]);
+                    return 字源决策变化::新建(vec![], vec![元素], vec![]);
                 }
             }
         }
@@ -155,21 +273,22 @@ impl 字源操作 {
 
     fn 移动字根(&self, 决策: &mut 字源决策) -> 字源决策变化 {
         let mut rng = rng();
-        for _ in 0..最大尝试次数 {
+        for _ in 0..self.配置.最大尝试次数 {
             let 元素 = *self.决策空间.字根.choose(&mut rng).unwrap();
             if 决策.元素[元素] == 字源元素安排::未选取 {
                 continue;
             }
-            // 蓄水池抽样
+            // 按打分加权的蓄水池抽样
             let mut 下一个安排 = None;
-            let mut count = 0;
+            let mut 总权重 = 0.0;
             for 条件安排 in &self.决策空间.元素[元素] {
                 if 条件安排.安排 != 决策.元素[元素]
                     && 条件安排.安排 != 字源元素安排::未选取
                     && 决策.允许(条件安排)
                 {
-                    count += 1;
-                    if random_range(0..count) == 0 {
+                    let 权重 = self.权重(条件安排);
+                    总权重 += 权重;
+                    if random::<f64>() * 总权重 < 权重 {
                         下一个安排 = Some(条件安排.安排);
                     }
                 }
@@ -181,4 +300,45 @@ impl 字源操作 {
         }
         字源决策变化::无变化()
     }
+
+    /// 随机选取若干个位于不同键位上的字根，将它们的键位依次轮换，数量为 2 时即为交换
+    fn 轮换(&self, 决策: &mut 字源决策, 数量: usize) -> 字源决策变化 {
+        let mut rng = rng();
+        for _ in 0..self.配置.最大尝试次数 {
+            let 字根列表: Vec<元素> = self
+                .决策空间
+                .字根
+                .choose_multiple(&mut rng, 数量)
+                .cloned()
+                .collect();
+            let 键位列表: Vec<char> = 字根列表
+                .iter()
+                .filter_map(|x| match 决策.元素[*x] {
+                    字源元素安排::键位(键位) => Some(键位),
+                    _ => None,
+                })
+                .collect();
+            let 不同键位: FxHashSet<_> = 键位列表.iter().collect();
+            if 字根列表.len() < 数量 || 不同键位.len() < 数量 {
+                continue;
+            }
+            let 原安排: Vec<_> = 字根列表.iter().map(|x| 决策.元素[*x]).collect();
+            for (序号, 字根) in 字根列表.iter().enumerate() {
+                决策.元素[*字根] = 字源元素安排::键位(键位列表[(序号 + 1) % 数量]);
+            }
+            // 全部字根就位后再检查条件，因为条件可能引用参与轮换的其他字根
+            let 合法 = 字根列表.iter().all(|字根| {
+                self.决策空间.元素[*字根]
+                    .iter()
+                    .any(|x| x.安排 == 决策.元素[*字根] && 决策.允许(x))
+            });
+            if 合法 {
+                return 字源决策变化::新建(vec![], vec![], 字根列表);
+            }
+            for (字根, 安排) in zip(&字根列表, &原安排) {
+                决策.元素[*字根] = *安排;
+            }
+        }
+        字源决策变化::无变化()
+    }
 }
@@ -0,0 +1,225 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+struct 边 {
+    终点: usize,
+    容量: i64,
+    费用: i64,
+    反向: usize,
+}
+
+struct 网络 {
+    邻接表: Vec<Vec<边>>,
+}
+
+impl 网络 {
+    fn 新建(节点数: usize) -> Self {
+        Self {
+            邻接表: (0..节点数).map(|_| vec![]).collect(),
+        }
+    }
+
+    fn 加边(&mut self, 起点: usize, 终点: usize, 容量: i64, 费用: i64) {
+        let 正向 = self.邻接表[起点].len();
+        let 反向 = self.邻接表[终点].len();
+        self.邻接表[起点].push(边 {
+            终点,
+            容量,
+            费用,
+            反向,
+        });
+        self.邻接表[终点].push(边 {
+            终点: 起点,
+            容量: 0,
+            费用: -费用,
+            反向: 正向,
+        });
+    }
+}
+
+/// 求简码槽位的最小费用分配。
+///
+/// 候选[词] 是这个词可以选用的槽位和选用后的费用，费用为负数表示能节省的键数；
+/// 容量[槽位] 是每个槽位最多容纳的词数。每个词最多分到一个槽位，返回每个词分到的槽位。
+///
+/// 用带势函数的 Dijkstra 逐次寻找最短增广路，增广路费用不再为负时停止
+pub fn 最优分配(候选: &[Vec<(usize, i64)>], 容量: &[u8]) -> Vec<Option<usize>> {
+    let 词数 = 候选.len();
+    let 源点 = 0;
+    let 汇点 = 词数 + 容量.len() + 1;
+    let 槽位节点 = |槽位: usize| 词数 + 1 + 槽位;
+    let mut 网络 = 网络::新建(汇点 + 1);
+    for (词, 词候选) in 候选.iter().enumerate() {
+        if 词候选.is_empty() {
+            continue;
+        }
+        网络.加边(源点, 词 + 1, 1, 0);
+        for (槽位, 费用) in 词候选 {
+            网络.加边(词 + 1, 槽位节点(*槽位), 1, *费用);
+        }
+    }
+    for (槽位, 数量) in 容量.iter().enumerate() {
+        网络.加边(槽位节点(槽位), 汇点, *数量 as i64, 0);
+    }
+    // 初始的网络是分层的，可以直接求出使约化费用非负的势
+    let mut 势 = vec![0; 汇点 + 1];
+    for 词候选 in 候选 {
+        for (槽位, 费用) in 词候选 {
+            let 节点 = 槽位节点(*槽位);
+            势[节点] = 势[节点].min(*费用);
+        }
+    }
+    势[汇点] = 势[词数 + 1..汇点].iter().copied().min().unwrap_or(0);
+    let mut 距离 = vec![i64::MAX; 汇点 + 1];
+    let mut 前驱 = vec![(0, 0); 汇点 + 1];
+    loop {
+        距离.fill(i64::MAX);
+        距离[源点] = 0;
+        let mut 堆 = BinaryHeap::new();
+        堆.push(Reverse((0, 源点)));
+        while let Some(Reverse((当前距离, 节点))) = 堆.pop() {
+            if 当前距离 > 距离[节点] {
+                continue;
+            }
+            for (边序号, 边) in 网络.邻接表[节点].iter().enumerate() {
+                if 边.容量 == 0 {
+                    continue;
+                }
+                let 新距离 = 当前距离 + 边.费用 + 势[节点] - 势[边.终点];
+                if 新距离 < 距离[边.终点] {
+                    距离[边.终点] = 新距离;
+                    前驱[边.终点] = (节点, 边序号);
+                    堆.push(Reverse((新距离, 边.终点)));
+                }
+            }
+        }
+        if 距离[汇点] == i64::MAX {
+            break;
+        }
+        for 节点 in 0..=汇点 {
+            if 距离[节点] != i64::MAX {
+                势[节点] += 距离[节点];
+            }
+        }
+        // 源点的势始终为 0，汇点的势即为这条增广路的实际费用
+        if 势[汇点] >= 0 {
+            break;
+        }
+        let mut 节点 = 汇点;
+        while 节点 != 源点 {
+            let (上一节点, 边序号) = 前驱[节点];
+            let 边 = &mut 网络.邻接表[上一节点][边序号];
+            边.容量 -= 1;
+            let 反向 = 边.反向;
+            网络.邻接表[节点][反向].容量 += 1;
+            节点 = 上一节点;
+        }
+    }
+    (0..词数)
+        .map(|词| {
+            网络.邻接表[词 + 1]
+                .iter()
+                .find(|边| 边.终点 > 词数 && 边.终点 < 汇点 && 边.费用 <= 0 && 边.容量 == 0)
+                .map(|边| 边.终点 - 词数 - 1)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::最优分配;
+
+    fn 总费用(候选: &[Vec<(usize, i64)>], 分配: &[Option<usize>]) -> i64 {
+        候选
+            .iter()
+            .zip(分配)
+            .filter_map(|(词候选, 槽位)| {
+                let 槽位 = (*槽位)?;
+                词候选.iter().find(|x| x.0 == 槽位).map(|x| x.1)
+            })
+            .sum()
+    }
+
+    fn 检查容量(分配: &[Option<usize>], 容量: &[u8]) {
+        for (槽位, 数量) in 容量.iter().enumerate() {
+            let 占用 = 分配.iter().filter(|x| **x == Some(槽位)).count();
+            assert!(占用 <= *数量 as usize, "槽位 {槽位} 超出容量");
+        }
+    }
+
+    /// 枚举所有分配求最小费用，只用于很小的实例
+    fn 穷举最小费用(候选: &[Vec<(usize, i64)>], 容量: &[u8]) -> i64 {
+        fn 递归(候选: &[Vec<(usize, i64)>], 剩余: &mut Vec<u8>) -> i64 {
+            let Some((词候选, 其余)) = 候选.split_first() else {
+                return 0;
+            };
+            let mut 最小 = 递归(其余, 剩余);
+            for (槽位, 费用) in 词候选 {
+                if 剩余[*槽位] > 0 {
+                    剩余[*槽位] -= 1;
+                    最小 = 最小.min(费用 + 递归(其余, 剩余));
+                    剩余[*槽位] += 1;
+                }
+            }
+            最小
+        }
+        递归(候选, &mut 容量.to_vec())
+    }
+
+    #[test]
+    fn 最优分配优于贪心() {
+        // 贪心时第一个词占用槽位 0，第二个词无处可去，共节省 10；
+        // 最优是第一个词让出槽位 0 改用槽位 1，共节省 14
+        let 候选 = vec![vec![(0, -10), (1, -9)], vec![(0, -5)]];
+        let 容量 = [1, 1];
+        let 分配 = 最优分配(&候选, &容量);
+        assert_eq!(分配, vec![Some(1), Some(0)]);
+        assert_eq!(总费用(&候选, &分配), -14);
+    }
+
+    #[test]
+    fn 槽位容量大于一() {
+        let 候选 = vec![vec![(0, -3)], vec![(0, -2)], vec![(0, -1)]];
+        let 容量 = [2];
+        let 分配 = 最优分配(&候选, &容量);
+        assert_eq!(分配, vec![Some(0), Some(0), None]);
+    }
+
+    #[test]
+    fn 没有候选的词() {
+        let 候选 = vec![vec![], vec![(0, -1)], vec![]];
+        let 容量 = [1];
+        let 分配 = 最优分配(&候选, &容量);
+        assert_eq!(分配, vec![None, Some(0), None]);
+    }
+
+    #[test]
+    fn 与穷举结果一致() {
+        let 实例: Vec<(Vec<Vec<(usize, i64)>>, Vec<u8>)> = vec![
+            (
+                vec![
+                    vec![(0, -8), (1, -6), (2, -1)],
+                    vec![(0, -7), (1, -5)],
+                    vec![(0, -6)],
+                    vec![(1, -4), (2, -3)],
+                    vec![],
+                ],
+                vec![1, 1, 1],
+            ),
+            (
+                vec![
+                    vec![(0, -9), (1, -2)],
+                    vec![(0, -9), (2, -8)],
+                    vec![(1, -7), (2, -7)],
+                    vec![(0, -1), (1, -1), (2, -1)],
+                ],
+                vec![2, 1, 1],
+            ),
+        ];
+        for (候选, 容量) in &实例 {
+            let 分配 = 最优分配(候选, 容量);
+            检查容量(&分配, 容量);
+            assert_eq!(总费用(候选, &分配), 穷举最小费用(候选, 容量));
+        }
+    }
+}
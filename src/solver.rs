@@ -0,0 +1,256 @@
+use crate::config::{字源求解器配置, 延迟接受参数, 禁忌搜索参数};
+use crate::context::{字源上下文, 字源决策, 字源决策变化};
+use crate::objective::{字源指标, 字源目标函数};
+use crate::operator::字源操作;
+use chai::config::SolverConfig;
+use chai::contexts::上下文;
+use chai::interfaces::command_line::命令行;
+use chai::objectives::目标函数;
+use chai::operators::变异;
+use chai::optimizers::决策;
+use chai::{元素, 错误};
+use std::fs::write;
+
+const 报告间隔: usize = 1000;
+
+pub struct 字源优化结果 {
+    pub 映射: 字源决策,
+    pub 指标: 字源指标,
+    pub 分数: f64,
+}
+
+#[derive(Clone)]
+pub enum 字源求解器 {
+    退火(SolverConfig),
+    延迟接受爬山(延迟接受参数),
+    禁忌搜索(禁忌搜索参数),
+}
+
+impl 字源求解器 {
+    pub fn 新建(上下文: &字源上下文) -> Result<Self, 错误> {
+        let 求解器配置 = 上下文.字源配置.optimization.求解器.clone();
+        match 求解器配置.unwrap_or(字源求解器配置::退火) {
+            字源求解器配置::退火 => {
+                let 退火配置 = 上下文
+                    .配置
+                    .optimization
+                    .as_ref()
+                    .and_then(|x| x.metaheuristic.clone())
+                    .ok_or_else(|| {
+                        错误::from(
+                            "使用模拟退火时需要在 optimization.metaheuristic 中配置退火参数"
+                                .to_string(),
+                        )
+                    })?;
+                Ok(字源求解器::退火(退火配置))
+            }
+            字源求解器配置::延迟接受爬山(参数) => Ok(字源求解器::延迟接受爬山(参数)),
+            字源求解器配置::禁忌搜索(参数) => Ok(字源求解器::禁忌搜索(参数)),
+        }
+    }
+
+    pub fn 优化(
+        &self,
+        初始决策: &字源决策,
+        目标函数: &mut 字源目标函数,
+        操作: &mut 字源操作,
+        上下文: &字源上下文,
+        命令行: &命令行,
+    ) -> 字源优化结果 {
+        match self {
+            字源求解器::退火(退火配置) => {
+                let SolverConfig::SimulatedAnnealing(退火) = 退火配置;
+                let 结果 = 退火.优化(初始决策, 目标函数, 操作, 上下文, 命令行);
+                字源优化结果 {
+                    映射: 结果.映射,
+                    指标: 结果.指标,
+                    分数: 结果.分数,
+                }
+            }
+            字源求解器::延迟接受爬山(参数) => {
+                let 结果 = 延迟接受爬山(参数, 初始决策, 目标函数, 操作);
+                保存结果(&结果, 上下文, 命令行);
+                结果
+            }
+            字源求解器::禁忌搜索(参数) => {
+                let 结果 = 禁忌搜索(参数, 初始决策, 目标函数, 操作);
+                保存结果(&结果, 上下文, 命令行);
+                结果
+            }
+        }
+    }
+}
+
+/// 目标函数中编码器的状态总是对应最近一次求值的决策，而它不一定是当前决策。
+/// 这里记录从当前决策到编码器状态的差异，下一次求值时将其合并进决策变化
+struct 求值器<'a> {
+    目标函数: &'a mut 字源目标函数,
+    差异: Option<字源决策变化>,
+}
+
+impl<'a> 求值器<'a> {
+    fn 新建(目标函数: &'a mut 字源目标函数) -> Self {
+        Self {
+            目标函数,
+            差异: None,
+        }
+    }
+
+    /// 对「当前决策 + 变化」得到的候选决策求值
+    fn 求值(
+        &mut self, 候选决策: &字源决策, 变化: &字源决策变化
+    ) -> (字源指标, f64) {
+        let 实际变化 = match self.差异.take() {
+            Some(差异) => 字源决策::除法(&差异, 变化),
+            None => 变化.clone(),
+        };
+        let 结果 = self.目标函数.计算(候选决策, &Some(实际变化));
+        self.差异 = Some(变化.clone());
+        结果
+    }
+
+    /// 最近一次求值的候选决策成为当前决策
+    fn 接受(&mut self) {
+        self.差异 = None;
+    }
+
+    /// 某个较早求值的候选决策成为当前决策
+    fn 转移(&mut self, 变化: &字源决策变化) {
+        let 差异 = self.差异.take().unwrap_or_else(字源决策变化::无变化);
+        self.差异 = Some(字源决策::除法(变化, &差异));
+    }
+}
+
+fn 涉及元素(变化: &字源决策变化) -> impl Iterator<Item = &元素> {
+    变化
+        .增加字根
+        .iter()
+        .chain(变化.减少字根.iter())
+        .chain(变化.移动字根.iter())
+}
+
+fn 保存结果(结果: &字源优化结果, 上下文: &字源上下文, 命令行: &命令行) {
+    let 配置 = 上下文.序列化(&结果.映射);
+    write(命令行.输出目录.join("config.yaml"), 配置).unwrap();
+}
+
+/// 延迟接受爬山：候选解不差于当前解或若干步之前的当前解时接受
+fn 延迟接受爬山(
+    参数: &延迟接受参数,
+    初始决策: &字源决策,
+    目标函数: &mut 字源目标函数,
+    操作: &mut 字源操作,
+) -> 字源优化结果 {
+    let mut 当前决策 = 初始决策.clone();
+    let (指标, 分数) = 目标函数.计算(&当前决策, &None);
+    let mut 当前分数 = 分数;
+    let mut 最优 = 字源优化结果 {
+        映射: 当前决策.clone(),
+        指标,
+        分数,
+    };
+    let mut 历史分数 = vec![当前分数; 参数.延迟长度.max(1)];
+    let mut 求值器 = 求值器::新建(目标函数);
+    for 步数 in 0..参数.步数 {
+        let mut 候选决策 = 当前决策.clone();
+        let 变化 = 操作.变异(&mut 候选决策);
+        let (指标, 分数) = 求值器.求值(&候选决策, &变化);
+        let 位置 = 步数 % 历史分数.len();
+        if 分数 <= 当前分数 || 分数 <= 历史分数[位置] {
+            求值器.接受();
+            当前决策 = 候选决策;
+            当前分数 = 分数;
+            if 分数 < 最优.分数 {
+                最优 = 字源优化结果 {
+                    映射: 当前决策.clone(),
+                    指标,
+                    分数,
+                };
+            }
+        }
+        历史分数[位置] = 当前分数;
+        if (步数 + 1) % 报告间隔 == 0 {
+            println!(
+                "步数 {}：当前分数 {:.4}，最优分数 {:.4}",
+                步数 + 1,
+                当前分数,
+                最优.分数
+            );
+        }
+    }
+    最优
+}
+
+/// 禁忌搜索：每一步在若干个候选变异中选择最好的一个，近期移动过的元素不能再次移动，
+/// 除非能得到比历史最优更好的解
+fn 禁忌搜索(
+    参数: &禁忌搜索参数,
+    初始决策: &字源决策,
+    目标函数: &mut 字源目标函数,
+    操作: &mut 字源操作,
+) -> 字源优化结果 {
+    let mut 当前决策 = 初始决策.clone();
+    let (指标, 分数) = 目标函数.计算(&当前决策, &None);
+    let mut 当前分数 = 分数;
+    let mut 最优 = 字源优化结果 {
+        映射: 当前决策.clone(),
+        指标,
+        分数,
+    };
+    let mut 禁忌到期 = vec![0; 当前决策.元素.len()];
+    // 候选都从当前决策出发，不能由决策推断哪个被接受，改为挑选后逐个告知
+    操作.启用显式统计();
+    let mut 求值器 = 求值器::新建(目标函数);
+    for 步数 in 0..参数.步数 {
+        let mut 最佳候选: Option<(字源决策, 字源决策变化, 字源指标, f64)> = None;
+        let mut 候选操作 = vec![];
+        let mut 最佳序号 = None;
+        for _ in 0..参数.邻域大小 {
+            let mut 候选决策 = 当前决策.clone();
+            let 变化 = 操作.变异(&mut 候选决策);
+            if 涉及元素(&变化).next().is_none() {
+                continue;
+            }
+            候选操作.push(操作.上次操作());
+            let (指标, 分数) = 求值器.求值(&候选决策, &变化);
+            let 禁忌 = 涉及元素(&变化).any(|x| 禁忌到期[*x] > 步数);
+            if 禁忌 && 分数 >= 最优.分数 {
+                continue;
+            }
+            if 最佳候选.as_ref().is_none_or(|x| 分数 < x.3) {
+                最佳候选 = Some((候选决策, 变化, 指标, 分数));
+                最佳序号 = Some(候选操作.len() - 1);
+            }
+        }
+        for (序号, 操作序号) in 候选操作.iter().enumerate() {
+            if let Some(操作序号) = 操作序号 {
+                操作.更新接受率(*操作序号, 最佳序号 == Some(序号));
+            }
+        }
+        let Some((候选决策, 变化, 指标, 分数)) = 最佳候选 else {
+            continue;
+        };
+        求值器.转移(&变化);
+        for 元素 in 涉及元素(&变化) {
+            禁忌到期[*元素] = 步数 + 参数.禁忌长度;
+        }
+        当前决策 = 候选决策;
+        当前分数 = 分数;
+        if 分数 < 最优.分数 {
+            最优 = 字源优化结果 {
+                映射: 当前决策.clone(),
+                指标,
+                分数,
+            };
+        }
+        if (步数 + 1) % 报告间隔 == 0 {
+            println!(
+                "步数 {}：当前分数 {:.4}，最优分数 {:.4}",
+                步数 + 1,
+                当前分数,
+                最优.分数
+            );
+        }
+    }
+    最优
+}